@@ -0,0 +1,70 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use clap::Parser;
+use simple_ball_engine::arena::{Arena, VerticalScale};
+use simple_ball_engine::dimension::Dimension;
+use simple_ball_engine::material::BallMix;
+use simple_ball_engine::placement::Placement;
+use simple_ball_engine::terrain::{Terrain, ElevationEncoding};
+use simple_ball_engine::tuning::Tuning;
+
+const EXAMPLE_TERRAIN: &str = concat!(env!("CARGO_MANIFEST_DIR"),
+    "/../src/data/guide-access-elevation-data-example-response-960-5d3c885c50fbb3feea782f36bf241b87.png");
+
+/// Reports how many steps per second can be simulated for different numbers of balls,
+/// with each tuning, on the terrain the web app uses. Best run with --release
+#[derive(Parser, Debug)]
+struct Args {
+    /// Terrain RGB PNG to use as the floor, which is halved twice, as in the web app
+    #[arg(long, default_value = EXAMPLE_TERRAIN)]
+    terrain: PathBuf,
+
+    /// Numbers of balls to try, e.g. 1000,5000
+    #[arg(long, value_delimiter = ',', default_value = "1000,5000,10000")]
+    num_balls: Vec<u32>,
+
+    /// Steps to take before timing, so that balls have landed and started to pile up
+    #[arg(long, default_value_t = 300)]
+    settle_steps: usize,
+
+    /// Steps to time
+    #[arg(long, default_value_t = 300)]
+    steps: usize,
+
+    #[arg(long, default_value_t = 0)]
+    seed: u64
+}
+
+fn main() -> io::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let data = std::fs::read(&args.terrain)?;
+    let terrain = Terrain::from_png_image(data, ElevationEncoding::TerrainRgb, 0.0, 0.0)
+        .halfed()
+        .halfed();
+    let dimension = Dimension::with_aspect_ratio(50.0, terrain.width, terrain.height);
+    let tunings = [("accurate", Tuning::accurate()), ("many-bodies", Tuning::many_bodies())];
+
+    println!("{:>8} {:>12} {:>12} {:>10}", "balls", "tuning", "steps/sec", "sleeping");
+    for num_balls in &args.num_balls {
+        for (name, tuning) in tunings {
+            let mut arena = Arena::new(dimension, *num_balls, &terrain, args.seed,
+                VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+            arena.physics.tune(tuning);
+            for _ in 0..args.settle_steps {
+                arena.step();
+            }
+            let start = Instant::now();
+            for _ in 0..args.steps {
+                arena.step();
+            }
+            let steps_per_second = args.steps as f64 / start.elapsed().as_secs_f64();
+            println!("{:>8} {:>12} {:>12.1} {:>10}",
+                arena.physics.num_balls(), name, steps_per_second, arena.physics.num_sleeping_balls());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,391 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use simple_ball_engine::arena::{Arena, VerticalScale};
+use simple_ball_engine::dimension::Dimension;
+use simple_ball_engine::material::{BallKind, BallMix};
+use simple_ball_engine::placement::Placement;
+use simple_ball_engine::resample::ResampleMethod;
+use simple_ball_engine::terrain::{Terrain, ElevationEncoding};
+use simple_ball_engine::tuning::Tuning;
+
+mod schedule;
+
+use schedule::*;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Encoding {
+    TerrainRgb,
+    Terrarium,
+    Grayscale16
+}
+
+impl From<Encoding> for ElevationEncoding {
+    fn from(encoding: Encoding) -> ElevationEncoding {
+        match encoding {
+            Encoding::TerrainRgb => ElevationEncoding::TerrainRgb,
+            Encoding::Terrarium => ElevationEncoding::Terrarium,
+            Encoding::Grayscale16 => ElevationEncoding::Grayscale16
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Method {
+    Nearest,
+    Bilinear,
+    Bicubic
+}
+
+impl From<Method> for ResampleMethod {
+    fn from(method: Method) -> ResampleMethod {
+        match method {
+            Method::Nearest => ResampleMethod::Nearest,
+            Method::Bilinear => ResampleMethod::Bilinear,
+            Method::Bicubic => ResampleMethod::Bicubic
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Strategy {
+    Uniform,
+    LowBiased,
+    HighBiased,
+    Points,
+    PoissonDisk,
+    Contour,
+    Basins
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Kind {
+    Ball,
+    Sand,
+    Pebble,
+    Boulder
+}
+
+impl From<Kind> for BallKind {
+    fn from(kind: Kind) -> BallKind {
+        match kind {
+            Kind::Ball => BallKind::ball(),
+            Kind::Sand => BallKind::sand(),
+            Kind::Pebble => BallKind::pebble(),
+            Kind::Boulder => BallKind::boulder()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Preset {
+    Accurate,
+    ManyBodies
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Jsonl
+}
+
+/// Runs a terrain scenario headless and writes every ball's translation at every step
+#[derive(Parser, Debug)]
+struct Args {
+    /// Terrain to use as the floor, one of a PNG (see --encoding),
+    /// GeoTIFF (.tif), ESRI ASCII grid (.asc) or SRTM (.hgt)
+    #[arg(long)]
+    terrain: PathBuf,
+
+    /// How elevations are encoded in a PNG terrain
+    #[arg(long, value_enum, default_value_t = Encoding::TerrainRgb)]
+    encoding: Encoding,
+
+    /// Elevation of black in a grayscale16 PNG terrain
+    #[arg(long, default_value_t = 0.0)]
+    min_elevation: f32,
+
+    /// Elevation of white in a grayscale16 PNG terrain
+    #[arg(long, default_value_t = 0.0)]
+    max_elevation: f32,
+
+    /// Crop the terrain to the window of WIDTHxHEIGHT cells whose top-left is X,Y, as X,Y,WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_crop)]
+    crop: Option<(usize, usize, usize, usize)>,
+
+    /// Fill depressions shallower than this many metres, taking them to be artefacts of the data
+    #[arg(long)]
+    fill_depressions_shallower_than: Option<f32>,
+
+    /// Halve the terrain until it is at most this many cells on each side
+    #[arg(long)]
+    max_terrain_size: Option<usize>,
+
+    /// Resample the terrain to exactly WIDTHxHEIGHT cells, e.g. 300x200
+    #[arg(long, value_parser = parse_size)]
+    resample: Option<(usize, usize)>,
+
+    /// How to filter when resampling
+    #[arg(long, value_enum, default_value_t = Method::Bilinear)]
+    resample_method: Method,
+
+    /// Length of the longest side of the arena, the other side follows the terrain's aspect ratio
+    #[arg(long, default_value_t = 50.0)]
+    arena_size: f32,
+
+    #[arg(long, default_value_t = 200)]
+    num_balls: u32,
+
+    #[arg(long, default_value_t = 600)]
+    steps: usize,
+
+    /// Use the terrain's real-world heights, multiplied by this, rather than normalised heights.
+    /// Needs a terrain whose size in metres is known
+    #[arg(long)]
+    vertical_exaggeration: Option<f32>,
+
+    /// How to place balls at the start
+    #[arg(long, value_enum, default_value_t = Strategy::LowBiased)]
+    placement: Strategy,
+
+    /// Where to place a ball with --placement points, as X,Y in terrain cells (can be repeated)
+    #[arg(long = "point", value_parser = parse_point)]
+    points: Vec<(f32, f32)>,
+
+    /// Least distance between balls with --placement poisson-disk, in ball diameters
+    #[arg(long, default_value_t = 2.0)]
+    poisson_spacing: f32,
+
+    /// Elevation to place balls along with --placement contour
+    #[arg(long)]
+    contour_elevation: Option<f32>,
+
+    /// Depth in metres below which depressions are ignored with --placement basins,
+    /// taking them to be artefacts of the data
+    #[arg(long, default_value_t = 0.0)]
+    basin_min_depth: f32,
+
+    /// Kind of ball to include in the mix, with how likely each ball is to be of that kind,
+    /// as KIND:WEIGHT e.g. sand:3 (can be repeated). Defaults to only standard balls
+    #[arg(long = "ball", value_parser = parse_ball)]
+    balls: Vec<(Kind, f64)>,
+
+    /// Trade-off between accuracy and how many balls can be simulated, see also --ccd,
+    /// --solver-iterations and --sleeping, which override it
+    #[arg(long, value_enum, default_value_t = Preset::Accurate)]
+    tuning: Preset,
+
+    /// Continuous collision detection for every ball
+    #[arg(long)]
+    ccd: Option<bool>,
+
+    /// Solver iterations per step
+    #[arg(long)]
+    solver_iterations: Option<usize>,
+
+    /// Whether balls at rest may sleep
+    #[arg(long)]
+    sleeping: Option<bool>,
+
+    /// Seed for ball placement, so that runs can be reproduced
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Force to apply to every ball from a step onwards, as STEP:X,Z (can be repeated)
+    #[arg(long = "force", value_parser = parse_force_change)]
+    forces: Vec<ForceChange>,
+
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+
+    /// Where to write trajectories, defaults to stdout
+    #[arg(long)]
+    output: Option<PathBuf>
+}
+
+// parses "WIDTHxHEIGHT" e.g. "300x200"
+fn parse_size(s: &str) -> Result<(usize, usize), String> {
+    let (width, height) = s.split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT but got '{}'", s))?;
+    let width = width.trim().parse::<usize>().map_err(|e| format!("bad width '{}': {}", width, e))?;
+    let height = height.trim().parse::<usize>().map_err(|e| format!("bad height '{}': {}", height, e))?;
+    if width == 0 || height == 0 {
+        return Err(format!("expected a non-empty size but got '{}'", s));
+    }
+    Ok((width, height))
+}
+
+// parses "X,Y,WIDTHxHEIGHT" e.g. "100,50,300x200"
+fn parse_crop(s: &str) -> Result<(usize, usize, usize, usize), String> {
+    let mut parts = s.splitn(3, ',');
+    let (x, y, size) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(x), Some(y), Some(size)) => (x, y, size),
+        _ => return Err(format!("expected X,Y,WIDTHxHEIGHT but got '{}'", s))
+    };
+    let x = x.trim().parse::<usize>().map_err(|e| format!("bad x '{}': {}", x, e))?;
+    let y = y.trim().parse::<usize>().map_err(|e| format!("bad y '{}': {}", y, e))?;
+    let (width, height) = parse_size(size)?;
+    Ok((x, y, width, height))
+}
+
+// parses "X,Y" e.g. "10.5,20"
+fn parse_point(s: &str) -> Result<(f32, f32), String> {
+    let (x, y) = s.split_once(',')
+        .ok_or_else(|| format!("expected X,Y but got '{}'", s))?;
+    let x = x.trim().parse::<f32>().map_err(|e| format!("bad x '{}': {}", x, e))?;
+    let y = y.trim().parse::<f32>().map_err(|e| format!("bad y '{}': {}", y, e))?;
+    Ok((x, y))
+}
+
+// parses "KIND:WEIGHT" e.g. "sand:3"
+fn parse_ball(s: &str) -> Result<(Kind, f64), String> {
+    let (kind, weight) = s.split_once(':')
+        .ok_or_else(|| format!("expected KIND:WEIGHT but got '{}'", s))?;
+    let kind = Kind::from_str(kind.trim(), true)?;
+    let weight = weight.trim().parse::<f64>().map_err(|e| format!("bad weight '{}': {}", weight, e))?;
+    if weight.is_nan() || weight <= 0.0 || weight.is_infinite() {
+        return Err(format!("expected a weight more than 0 but got '{}'", weight));
+    }
+    Ok((kind, weight))
+}
+
+fn mix(args: &Args) -> io::Result<BallMix> {
+    if args.balls.is_empty() {
+        return Ok(BallMix::default());
+    }
+    args.balls.iter()
+        .try_fold(BallMix::new(), |mix, (kind, weight)| mix.with((*kind).into(), *weight))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn tuning(args: &Args) -> Tuning {
+    let preset = match args.tuning {
+        Preset::Accurate => Tuning::accurate(),
+        Preset::ManyBodies => Tuning::many_bodies()
+    };
+    Tuning {
+        ccd: args.ccd.unwrap_or(preset.ccd),
+        solver_iterations: args.solver_iterations.unwrap_or(preset.solver_iterations),
+        sleeping: args.sleeping.unwrap_or(preset.sleeping),
+        ..preset
+    }
+}
+
+fn placement(args: &Args) -> io::Result<Placement> {
+    Ok(match args.placement {
+        Strategy::Uniform => Placement::uniform(),
+        Strategy::LowBiased => Placement::low_biased(),
+        Strategy::HighBiased => Placement::high_biased(),
+        Strategy::Points => {
+            let (xs, ys) = args.points.iter().cloned().unzip();
+            Placement::points(xs, ys).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        },
+        Strategy::PoissonDisk => Placement::poisson_disk(args.poisson_spacing)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        Strategy::Contour => {
+            let elevation = args.contour_elevation.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                "--placement contour needs --contour-elevation"))?;
+            Placement::contour(elevation)
+        },
+        Strategy::Basins => Placement::basins(args.basin_min_depth)
+    })
+}
+
+fn load_terrain(args: &Args) -> io::Result<Terrain> {
+    let extension = args.terrain.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    let data = std::fs::read(&args.terrain)?;
+    let terrain = match extension.as_deref() {
+        Some("tif") | Some("tiff") => Terrain::from_geotiff(data),
+        Some("asc") => Terrain::from_esri_ascii_grid(&String::from_utf8_lossy(&data)),
+        Some("hgt") => {
+            let file_name = args.terrain.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+            Terrain::from_srtm_hgt(data, &file_name)
+        },
+        _ => Ok(Terrain::from_png_image(data, args.encoding.into(), args.min_elevation, args.max_elevation))
+    };
+    terrain.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_header(out: &mut dyn Write, format: Format) -> io::Result<()> {
+    match format {
+        Format::Csv => writeln!(out, "step,ball,x,y,z,radius"),
+        Format::Jsonl => Ok(())
+    }
+}
+
+fn write_translations(out: &mut dyn Write, format: Format, step: usize, arena: &Arena) -> io::Result<()> {
+    let radii = arena.physics.ball_radii();
+    for (ball, (translation, radius)) in arena.physics.ball_translations().iter().zip(radii).enumerate() {
+        let (x, y, z) = (translation.x, translation.y, translation.z);
+        match format {
+            Format::Csv => writeln!(out, "{},{},{},{},{},{}", step, ball, x, y, z, radius)?,
+            Format::Jsonl => writeln!(out,
+                "{{\"step\":{},\"ball\":{},\"x\":{},\"y\":{},\"z\":{},\"radius\":{}}}", step, ball, x, y, z, radius)?
+        }
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let mut terrain = load_terrain(&args)?;
+    if let Some((x, y, width, height)) = args.crop {
+        terrain = terrain.crop(x, y, width, height)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    }
+    if let Some(min_depth) = args.fill_depressions_shallower_than {
+        terrain = terrain.fill_shallow_depressions(min_depth);
+    }
+    if let Some(max_terrain_size) = args.max_terrain_size {
+        terrain = terrain.shrink_to_fit(max_terrain_size);
+    }
+    if let Some((width, height)) = args.resample {
+        terrain = terrain.resample(width, height, args.resample_method.into());
+    }
+    log::info!("Using terrain of {}x{}", terrain.width, terrain.height);
+
+    let dimension = Dimension::with_aspect_ratio(args.arena_size, terrain.width, terrain.height);
+    let vertical_scale = match args.vertical_exaggeration {
+        Some(exaggeration) => VerticalScale::RealWorld { exaggeration },
+        None => VerticalScale::Normalised
+    };
+    let mut arena = Arena::new(dimension, args.num_balls, &terrain, args.seed, vertical_scale, &placement(&args)?, &mix(&args)?);
+    arena.physics.tune(tuning(&args));
+    log::info!("Using {:?} and {:?}", arena.height_scale, arena.physics.tuning());
+    let schedule = ForceSchedule::new(&args.forces);
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock()))
+    };
+
+    // step 0 is the initial placement, before any simulation
+    write_header(&mut out, args.format)?;
+    write_translations(&mut out, args.format, 0, &arena)?;
+    for step in 1..=args.steps {
+        let (x, z) = schedule.force_at(step);
+        arena.physics.set_ball_force(x, z);
+        arena.step();
+        write_translations(&mut out, args.format, step, &arena)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod main_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ball() {
+        assert!(matches!(parse_ball("sand:3"), Ok((Kind::Sand, weight)) if weight == 3.0));
+        for bad in ["sand", "sand:x", "gravel:1", "sand:-1", "sand:0", "sand:NaN", "sand:inf"] {
+            assert!(parse_ball(bad).is_err(), "{}", bad);
+        }
+    }
+}
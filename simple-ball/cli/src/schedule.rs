@@ -0,0 +1,64 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForceChange {
+    pub from_step: usize,
+    pub x: f32,
+    pub z: f32
+}
+
+// parses "STEP:X,Z" e.g. "100:1.5,-2"
+pub fn parse_force_change(s: &str) -> Result<ForceChange, String> {
+    let (step, force) = s.split_once(':')
+        .ok_or_else(|| format!("expected STEP:X,Z but got '{}'", s))?;
+    let (x, z) = force.split_once(',')
+        .ok_or_else(|| format!("expected X,Z after ':' but got '{}'", force))?;
+    let from_step = step.trim().parse::<usize>().map_err(|e| format!("bad step '{}': {}", step, e))?;
+    let x = x.trim().parse::<f32>().map_err(|e| format!("bad x '{}': {}", x, e))?;
+    let z = z.trim().parse::<f32>().map_err(|e| format!("bad z '{}': {}", z, e))?;
+    Ok(ForceChange { from_step, x, z })
+}
+
+pub struct ForceSchedule {
+    changes: Vec<ForceChange>
+}
+
+impl ForceSchedule {
+    pub fn new(changes: &[ForceChange]) -> ForceSchedule {
+        let mut changes = changes.to_vec();
+        changes.sort_by_key(|change| change.from_step);
+        ForceSchedule { changes }
+    }
+
+    // the force in effect at step, or no force if no change has happened yet
+    pub fn force_at(&self, step: usize) -> (f32, f32) {
+        self.changes.iter()
+            .take_while(|change| change.from_step <= step)
+            .last()
+            .map_or((0.0, 0.0), |change| (change.x, change.z))
+    }
+}
+
+#[cfg(test)]
+mod schedule_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_force_change() {
+        let expected = ForceChange { from_step: 100, x: 1.5, z: -2.0 };
+        assert_eq!(Ok(expected), parse_force_change("100:1.5,-2"));
+        assert!(parse_force_change("100").is_err());
+        assert!(parse_force_change("100:1.5").is_err());
+        assert!(parse_force_change("a:1,2").is_err());
+    }
+
+    #[test]
+    fn test_force_at() {
+        let schedule = ForceSchedule::new(&[
+            ForceChange { from_step: 20, x: 0.0, z: 0.0 },
+            ForceChange { from_step: 10, x: 1.0, z: 2.0 }
+        ]);
+        assert_eq!((0.0, 0.0), schedule.force_at(0));
+        assert_eq!((1.0, 2.0), schedule.force_at(10));
+        assert_eq!((1.0, 2.0), schedule.force_at(19));
+        assert_eq!((0.0, 0.0), schedule.force_at(20));
+    }
+}
@@ -0,0 +1,144 @@
+// Grids derived from elevations, each the same shape as the elevations, which are
+// expected to be stored as for Terrain, i.e. x = columns, y = rows, where
+// x, y is in screen space, so that row 0 is north.
+// Cells beyond the edges are taken to be the same as the nearest edge cell
+use rapier3d::prelude::*;
+
+// aspect of cells with no slope
+pub const FLAT_ASPECT: Real = -1.0;
+
+// the 3x3 neighbourhood of a cell, in row-major order, i.e.
+// z[0] z[1] z[2]
+// z[3] z[4] z[5]
+// z[6] z[7] z[8]
+fn window(elevations: &DMatrix<Real>, row: usize, column: usize) -> [Real; 9] {
+    let last_row = elevations.nrows() - 1;
+    let last_column = elevations.ncols() - 1;
+    let rows = [row.saturating_sub(1), row, (row + 1).min(last_row)];
+    let columns = [column.saturating_sub(1), column, (column + 1).min(last_column)];
+    let mut z = [0.0; 9];
+    for (i, r) in rows.iter().enumerate() {
+        for (j, c) in columns.iter().enumerate() {
+            z[i * 3 + j] = elevations[(*r, *c)];
+        }
+    }
+    z
+}
+
+// rate of change of elevation eastwards and northwards, as in Horn (1981)
+fn gradient(z: &[Real; 9], cell_width: Real, cell_height: Real) -> (Real, Real) {
+    let east = ((z[2] + 2.0 * z[5] + z[8]) - (z[0] + 2.0 * z[3] + z[6])) / (8.0 * cell_width);
+    let north = ((z[0] + 2.0 * z[1] + z[2]) - (z[6] + 2.0 * z[7] + z[8])) / (8.0 * cell_height);
+    (east, north)
+}
+
+// steepest slope, in degrees from horizontal
+pub fn slope(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    DMatrix::from_fn(elevations.nrows(), elevations.ncols(), |row, column| {
+        let (east, north) = gradient(&window(elevations, row, column), cell_width, cell_height);
+        east.hypot(north).atan().to_degrees()
+    })
+}
+
+// direction the slope faces, i.e. downhill, in degrees clockwise from north,
+// or FLAT_ASPECT where there is no slope
+pub fn aspect(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    DMatrix::from_fn(elevations.nrows(), elevations.ncols(), |row, column| {
+        let (east, north) = gradient(&window(elevations, row, column), cell_width, cell_height);
+        if east == 0.0 && north == 0.0 {
+            FLAT_ASPECT
+        } else {
+            (-east).atan2(-north).to_degrees().rem_euclid(360.0)
+        }
+    })
+}
+
+// coefficients of the quadratic surface through a cell's neighbourhood,
+// as in Zevenbergen & Thorne (1987), returned as (D, E, F, G, H)
+fn quadratic(z: &[Real; 9], cell_width: Real, cell_height: Real) -> (Real, Real, Real, Real, Real) {
+    let d = ((z[3] + z[5]) / 2.0 - z[4]) / (cell_width * cell_width);
+    let e = ((z[1] + z[7]) / 2.0 - z[4]) / (cell_height * cell_height);
+    let f = (-z[0] + z[2] + z[6] - z[8]) / (4.0 * cell_width * cell_height);
+    let g = (z[5] - z[3]) / (2.0 * cell_width);
+    let h = (z[1] - z[7]) / (2.0 * cell_height);
+    (d, e, f, g, h)
+}
+
+// curvature in the direction of steepest slope, per unit of cell size.
+// Positive where the surface is convex, so flow speeds up, and
+// negative where it is concave, so flow slows down
+pub fn profile_curvature(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    DMatrix::from_fn(elevations.nrows(), elevations.ncols(), |row, column| {
+        let (d, e, f, g, h) = quadratic(&window(elevations, row, column), cell_width, cell_height);
+        let gradient_squared = g * g + h * h;
+        if gradient_squared == 0.0 {
+            0.0
+        } else {
+            -2.0 * (d * g * g + e * h * h + f * g * h) / gradient_squared
+        }
+    })
+}
+
+// curvature across the direction of steepest slope, per unit of cell size.
+// Positive where the surface is convex, so flow spreads out, and
+// negative where it is concave, so flow comes together
+pub fn plan_curvature(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    DMatrix::from_fn(elevations.nrows(), elevations.ncols(), |row, column| {
+        let (d, e, f, g, h) = quadratic(&window(elevations, row, column), cell_width, cell_height);
+        let gradient_squared = g * g + h * h;
+        if gradient_squared == 0.0 {
+            0.0
+        } else {
+            -2.0 * (d * h * h + e * g * g - f * g * h) / gradient_squared
+        }
+    })
+}
+
+#[cfg(test)]
+mod analysis_tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    fn assert_near(expected: Real, actual: Real) {
+        assert!((expected - actual).abs() < 0.001, "expected {} but was {}", expected, actual);
+    }
+
+    // z = x^2 + y^2, centred on the middle cell
+    fn bowl() -> DMatrix<Real> {
+        DMatrix::from_fn(5, 5, |row, column| {
+            let (x, y) = (column as Real - 2.0, 2.0 - row as Real);
+            x * x + y * y
+        })
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_plane() {
+        // rises by 1 per cell eastwards
+        let plane = DMatrix::from_fn(3, 3, |_, column| column as Real);
+        let (row, column) = (1, 1);
+        assert_near(45.0, slope(&plane, 1.0, 1.0)[(row, column)]);
+        assert_near(270.0, aspect(&plane, 1.0, 1.0)[(row, column)]);
+        // twice as wide cells make for half the slope
+        assert_near((0.5 as Real).atan().to_degrees(), slope(&plane, 2.0, 1.0)[(row, column)]);
+        assert_near(0.0, profile_curvature(&plane, 1.0, 1.0)[(row, column)]);
+        assert_near(0.0, plan_curvature(&plane, 1.0, 1.0)[(row, column)]);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_bowl() {
+        let bowl = bowl();
+        // east of the centre, facing back west into the bowl
+        assert_near(270.0, aspect(&bowl, 1.0, 1.0)[(2, 3)]);
+        // north of the centre, facing south
+        assert_near(180.0, aspect(&bowl, 1.0, 1.0)[(1, 2)]);
+        assert_eq!(FLAT_ASPECT, aspect(&bowl, 1.0, 1.0)[(2, 2)]);
+        // concave both along and across the slope
+        assert_near(-2.0, profile_curvature(&bowl, 1.0, 1.0)[(2, 3)]);
+        assert_near(-2.0, plan_curvature(&bowl, 1.0, 1.0)[(2, 3)]);
+        // and convex for a dome
+        let dome = -bowl;
+        assert_near(2.0, profile_curvature(&dome, 1.0, 1.0)[(2, 3)]);
+        assert_near(2.0, plan_curvature(&dome, 1.0, 1.0)[(2, 3)]);
+    }
+}
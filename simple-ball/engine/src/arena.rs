@@ -1,16 +1,25 @@
+use std::collections::HashMap;
 
 use rapier3d::prelude::*;
+use serde::{Serialize, Deserialize};
 
-use crate::log::*;
 use crate::terrain::*;
 use crate::dimension::*;
+use crate::placement::*;
+use crate::material::*;
+use crate::emitter::*;
+use crate::tuning::*;
+use crate::contacts::*;
 
 
+#[derive(Serialize, Deserialize)]
 pub struct RapierState {
     rigid_body_set:  RigidBodySet,
     collider_set:  ColliderSet,
     gravity: Vector<Real>,
     integration_parameters:  IntegrationParameters,
+    // holds no simulation state, only scratch space and counters
+    #[serde(skip, default = "PhysicsPipeline::new")]
     physics_pipeline:  PhysicsPipeline,
     island_manager:  IslandManager,
     broad_phase:  BroadPhase,
@@ -18,141 +27,209 @@ pub struct RapierState {
     impulse_joint_set:  ImpulseJointSet,
     multibody_joint_set:  MultibodyJointSet,
     ccd_solver:  CCDSolver,
-    ball_body_handles: Vec<RigidBodyHandle>,
-    ball_radius: f32
+    // in the order they were added
+    balls: Vec<Ball>,
+    next_ball_id: u32,
+    // radius of a standard ball
+    ball_radius: f32,
+    // applied to every ball, including those spawned later
+    ball_force: Vector<Real>,
+    tuning: Tuning,
+    surroundings: Surroundings,
+    // of every ball, to tell which balls are in contact
+    ball_by_collider: HashMap<ColliderHandle, u32>,
+    // of balls despawned since the last step, whose contacts rapier stops during the next one,
+    // and which like contacts are lost in a snapshot
+    #[serde(skip)]
+    despawned_by_collider: HashMap<ColliderHandle, u32>,
+    // None when not recording contacts, otherwise the least force to record
+    contact_force_threshold: Option<Real>,
+    // events not yet drained, which are lost in a snapshot
+    #[serde(skip)]
+    contacts: ContactCollector
 }
 
+#[derive(Serialize, Deserialize)]
+struct Ball {
+    // stays the same for as long as the ball exists, unlike its position in balls
+    id: u32,
+    handle: RigidBodyHandle,
+    radius: Real
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VerticalScale {
+    // heights squashed into a few ball radii, whatever the terrain
+    Normalised,
+    // heights in the same proportion to horizontal distances as in the
+    // real terrain, multiplied by exaggeration
+    RealWorld { exaggeration: f32 }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HeightScale {
+    // arena units between the lowest and highest points of the terrain
+    pub y_extent: Real,
+    // arena units per metre of elevation
+    pub units_per_metre: Real
+}
+
+impl HeightScale {
+    fn new(vertical_scale: VerticalScale, dimension: &Dimension, ball_radius: Real, terrain: &Terrain) -> HeightScale {
+        let elevations = terrain.elevations();
+        let elevation_range = elevations.max() - elevations.min();
+        let units_per_metre = match (vertical_scale, terrain.cell_width_metres()) {
+            (VerticalScale::RealWorld { exaggeration }, Some(cell_width_metres)) => {
+                let terrain_width_metres = cell_width_metres * (terrain.width as f64);
+                exaggeration * dimension.width / (terrain_width_metres as Real)
+            },
+            (vertical_scale, _) => {
+                if let VerticalScale::RealWorld { .. } = vertical_scale {
+                    log::warn!("Terrain has no known size in metres, so using normalised heights");
+                }
+                let y_extent = ball_radius * 2.0 * 2.0;
+                if elevation_range > 0.0 { y_extent / elevation_range } else { 0.0 }
+            }
+        };
+        HeightScale {
+            y_extent: elevation_range * units_per_metre,
+            units_per_metre
+        }
+    }
 
+    // height from which to drop balls, well clear of the terrain
+    pub fn drop_height(&self) -> Real {
+        (2.0 * self.y_extent).max(100.0)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Arena {
     pub dimension: Dimension,
-    pub physics: RapierState
+    pub height_scale: HeightScale,
+    pub physics: RapierState,
+    // input, like the force on balls, rather than state, so not part of a snapshot
+    #[serde(skip)]
+    emitters: Vec<Emitter>,
+    #[serde(skip)]
+    next_emitter_id: u32
 }
 
 impl Arena {
-    pub fn new(side_length: f32, num_balls: u8, terrain: &Terrain) -> Arena {
-        let default_y = 100.0;
-        let ball_radius = 0.01 * side_length;
-        let balls 
-            = Self::random_balls(num_balls, ball_radius, side_length, terrain, default_y);
-        let physics = RapierState::new(balls, ball_radius, side_length, terrain);
+    pub fn new(dimension: Dimension, num_balls: u32, terrain: &Terrain, seed: u64, vertical_scale: VerticalScale, placement: &Placement, mix: &BallMix) -> Arena {
+        let ball_radius = 0.01 * dimension.width.min(dimension.depth);
+        let height_scale = HeightScale::new(vertical_scale, &dimension, ball_radius, terrain);
+        let default_y = height_scale.drop_height();
+        // placed far enough apart for the largest kind of ball
+        let translations 
+            = placement.place(num_balls as usize, ball_radius * mix.max_radius(), &dimension, terrain, default_y, seed);
+        let kinds = mix.choose(translations.len(), seed);
+        let balls = translations.into_iter().zip(kinds).collect();
+        let physics = RapierState::new(balls, ball_radius, &dimension, &height_scale, terrain);
         Arena {
-            dimension: Dimension { side_length },
-            physics
-        }
-    }
-
-    fn random_balls(num_balls: u8, ball_radius: f32, side_length: f32, terrain: &Terrain, y: Real) -> Vec<Vector<Real>> {
-        use rand::seq::SliceRandom;
-        use rand::thread_rng;
-
-        let containing_box_side_length = ball_radius * 2.0;
-        let possible_grid_positions_per_axis = (side_length / containing_box_side_length).floor() as u32;
-        console_log!("possible_grid_positions_per_axis: {}", possible_grid_positions_per_axis);
-        let sized_terrain = terrain.shrink_to_fit(possible_grid_positions_per_axis as usize);
-        console_log!("Sized terrain: {}x{}", sized_terrain.width, sized_terrain.height);
-        let max_bucket_value = 20.0;
-        let heightfield = sized_terrain.as_xz_heightfield(max_bucket_value);
-        console_log!("Converted to heightfield, shape: {:?}", heightfield.shape());
-        let possible_grid_positions : Vec<(u32, u32)>
-            = (0..sized_terrain.height).into_iter().flat_map(|z| {
-                let row : Vec<(u32, u32)> 
-                    = (0..sized_terrain.width).into_iter().map(|x| {
-                        (x as u32, z as u32)
-                    }).collect();
-                row
-            }).collect();
-        console_log!("Created possible grid positions");
-        let probababilities : Vec<((u32, u32), f64)> 
-            = possible_grid_positions.iter().map(|(x, z)| {
-                let row = *z as usize;
-                let column = *x as usize;
-                let index = (row, column);
-                let bucketed_height = *heightfield.index(index) as f64;
-                let probability = 2.0f64.powf((max_bucket_value as f64) - bucketed_height);
-                console_log!("{:?} -> {:?}", bucketed_height, probability);
-                ((*x, *z), probability)
-            }).collect();
-        console_log!("Created probabilities: {:?}", probababilities);
-        let mut rng = thread_rng();
-        let selected
-            = probababilities.choose_multiple_weighted(
-                &mut rng, 
-                num_balls as usize, 
-                |(_point, probability)| *probability).unwrap();
-        let x_scale_up = side_length / (sized_terrain.width as f32);
-        let z_scale_up = side_length / (sized_terrain.height as f32);
-        selected.map(|((x, z), _probability)| {
-            vector![
-                ((*x as f32) * x_scale_up) + ball_radius, 
-                y, 
-                ((*z as f32) * z_scale_up) + ball_radius]
-        }).collect()
+            dimension,
+            height_scale,
+            physics,
+            emitters: Vec::new(),
+            next_emitter_id: 0
+        }
+    }
+
+    // pours balls_per_second balls of kind from above arena x, z, until removed, returning its id
+    pub fn add_emitter(&mut self, x: Real, z: Real, balls_per_second: Real, kind: BallKind) -> u32 {
+        let id = self.next_emitter_id;
+        self.next_emitter_id += 1;
+        self.emitters.push(Emitter::new(id, x, z, balls_per_second, kind));
+        id
+    }
+
+    // false if there is no such emitter
+    pub fn remove_emitter(&mut self, id: u32) -> bool {
+        let before = self.emitters.len();
+        self.emitters.retain(|emitter| emitter.id != id);
+        self.emitters.len() < before
+    }
+
+    // emits any balls due from emitters, then steps the physics
+    pub fn step(&mut self) {
+        let dt = self.physics.dt();
+        let y = self.height_scale.drop_height();
+        for emitter in &mut self.emitters {
+            emitter.emit(&mut self.physics, &self.dimension, y, dt);
+        }
+        self.physics.step();
+    }
+
+    pub fn snapshot(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
     }
 
+    // with no emitters, see take_emitters_from
+    pub fn restore(snapshot: &[u8]) -> Result<Arena, String> {
+        bincode::deserialize(snapshot).map_err(|e| format!("could not restore snapshot: {}", e))
+    }
+
+    // so that emitters carry on, with the same ids, in an arena restored in place of previous
+    pub fn take_emitters_from(&mut self, previous: &mut Arena) {
+        self.emitters = std::mem::take(&mut previous.emitters);
+        self.next_emitter_id = previous.next_emitter_id;
+    }
 }
 
 impl RapierState {
-    fn new(ball_translations: Vec<Vector<Real>>, ball_radius: Real, side_length: f32, terrain: &Terrain) -> RapierState {
+    fn new(balls: Vec<(Vector<Real>, BallKind)>, ball_radius: Real, dimension: &Dimension, height_scale: &HeightScale, terrain: &Terrain) -> RapierState {
 
-        console_log!("Creating RapierState");
+        log::info!("Creating RapierState");
 
-        let mut rigid_body_set = RigidBodySet::new();
+        let rigid_body_set = RigidBodySet::new();
         let mut collider_set = ColliderSet::new();
 
         let thickness = 0.1;
+        let (width, depth) = (dimension.width, dimension.depth);
 
         /* heightfield as ground */
-        let height_y_extent = ball_radius * 2.0 * 2.0;
         let ground_size 
-            = Vector::new(side_length, height_y_extent, side_length);
+            = Vector::new(width, height_scale.y_extent, depth);
         let heights 
             = terrain.as_xz_heightfield(1.0);
         let heightfield = ColliderBuilder::heightfield(heights, ground_size)
-            .translation(vector![0.5 * side_length, 0.0, 0.5 * side_length])
+            .translation(vector![0.5 * width, 0.0, 0.5 * depth])
             .build();
-        collider_set.insert(heightfield);
+        let terrain = collider_set.insert(heightfield);
 
         /* walls */
-        let wall_y_extent = 100.0;
-        let wall1 = ColliderBuilder::cuboid(thickness, wall_y_extent, side_length)
+        // well above where balls are dropped from, even when in layers
+        let wall_y_extent = 2.0 * height_scale.drop_height();
+        let wall1 = ColliderBuilder::cuboid(thickness, wall_y_extent, depth)
             .translation(vector![-thickness, 0.0, 0.0])
             .build();
-        let wall2 = ColliderBuilder::cuboid(thickness, wall_y_extent, side_length)
-            .translation(vector![side_length, 0.0, 0.0])
+        let wall2 = ColliderBuilder::cuboid(thickness, wall_y_extent, depth)
+            .translation(vector![width, 0.0, 0.0])
             .build();
-        let wall3 = ColliderBuilder::cuboid(side_length, wall_y_extent, thickness)
+        let wall3 = ColliderBuilder::cuboid(width, wall_y_extent, thickness)
             .translation(vector![0.0, 0.0, -thickness])
             .build();
-        let wall4 = ColliderBuilder::cuboid(side_length, wall_y_extent, thickness)
-            .translation(vector![0.0, 0.0, side_length])
+        let wall4 = ColliderBuilder::cuboid(width, wall_y_extent, thickness)
+            .translation(vector![0.0, 0.0, depth])
             .build();
-        collider_set.insert(wall1);
-        collider_set.insert(wall2);
-        collider_set.insert(wall3);
-        collider_set.insert(wall4);
+        let walls = vec![
+            collider_set.insert(wall1),
+            collider_set.insert(wall2),
+            collider_set.insert(wall3),
+            collider_set.insert(wall4)
+        ];
 
-        /* bouncing balls. */
-        let mut ball_body_handles : Vec<RigidBodyHandle> = Vec::new();
-        for ball_translation in ball_translations {
-            let rigid_body = RigidBodyBuilder::dynamic()
-                    .ccd_enabled(true)
-                    .translation(ball_translation)
-                    .build();
-            let collider = ColliderBuilder::ball(ball_radius)
-                .restitution(0.8)
-                .build();
-            let ball_body_handle = rigid_body_set.insert(rigid_body);
-            collider_set.insert_with_parent(collider, ball_body_handle, &mut rigid_body_set);
-            ball_body_handles.push(ball_body_handle);
-        }
 
         /* Create other structures necessary for the simulation. */
         let gravity = vector![0.0, -9.81, 0.0];
-        let browser_refreshes_per_second = 60.0;
-        let integration_parameters = IntegrationParameters { 
-            dt: 1.0 / browser_refreshes_per_second, 
+        let steps_per_second = 60.0;
+        let tuning = Tuning::default();
+        let mut integration_parameters = IntegrationParameters { 
+            dt: 1.0 / steps_per_second, 
             ..Default::default()
         };
+        tuning.apply_to_parameters(&mut integration_parameters);
         let physics_pipeline = PhysicsPipeline::new();
         let island_manager = IslandManager::new();
         let broad_phase = BroadPhase::new();
@@ -161,7 +238,7 @@ impl RapierState {
         let multibody_joint_set = MultibodyJointSet::new();
         let ccd_solver = CCDSolver::new();
 
-        RapierState {
+        let mut state = RapierState {
             rigid_body_set,
             collider_set,
             gravity,
@@ -173,39 +250,171 @@ impl RapierState {
             impulse_joint_set,
             multibody_joint_set,
             ccd_solver,
-            ball_body_handles,
-            ball_radius
+            balls: Vec::new(),
+            next_ball_id: 0,
+            ball_radius,
+            ball_force: Vector::zeros(),
+            tuning,
+            surroundings: Surroundings { terrain, walls },
+            ball_by_collider: HashMap::new(),
+            despawned_by_collider: HashMap::new(),
+            contact_force_threshold: None,
+            contacts: ContactCollector::default()
+        };
+
+        /* bouncing balls. */
+        for (ball_translation, kind) in balls {
+            state.spawn_ball(ball_translation, kind);
+        }
+        state
+    }
+
+    // adds a ball of kind at translation, returning its id
+    pub fn spawn_ball(&mut self, translation: Vector<Real>, kind: BallKind) -> u32 {
+        let mut rigid_body = RigidBodyBuilder::dynamic()
+                .translation(translation)
+                .build();
+        self.tuning.apply_to_body(&mut rigid_body);
+        rigid_body.add_force(self.ball_force, true);
+        let radius = kind.radius * self.ball_radius;
+        let mut collider = ColliderBuilder::ball(radius)
+            .density(kind.density)
+            .friction(kind.friction)
+            .restitution(kind.restitution)
+            .build();
+        record_contacts_of(&mut collider, self.contact_force_threshold);
+        let handle = self.rigid_body_set.insert(rigid_body);
+        let collider = self.collider_set.insert_with_parent(collider, handle, &mut self.rigid_body_set);
+        let id = self.next_ball_id;
+        self.next_ball_id += 1;
+        self.ball_by_collider.insert(collider, id);
+        self.balls.push(Ball { id, handle, radius });
+        id
+    }
+
+    // false if there is no such ball
+    pub fn despawn_ball(&mut self, id: u32) -> bool {
+        let index = match self.balls.iter().position(|ball| ball.id == id) {
+            Some(index) => index,
+            None => return false
+        };
+        let ball = self.balls.remove(index);
+        for collider in self.rigid_body_set[ball.handle].colliders() {
+            self.ball_by_collider.remove(collider);
+            self.despawned_by_collider.insert(*collider, id);
         }
+        self.rigid_body_set.remove(
+            ball.handle,
+            &mut self.island_manager,
+            &mut self.collider_set,
+            &mut self.impulse_joint_set,
+            &mut self.multibody_joint_set,
+            true);
+        true
+    }
+
+    // whether a ball of radius at translation would overlap no other ball
+    pub fn is_clear(&self, translation: &Vector<Real>, radius: Real) -> bool {
+        self.balls.iter().all(|ball| {
+            let distance = (self.rigid_body_set[ball.handle].translation() - translation).norm();
+            distance >= ball.radius + radius
+        })
     }
 
     pub fn set_ball_force(&mut self, x: f32, z: f32) {
         let default_y = (x.abs() + z.abs()) / 2.0;
-        for ball_body_handle in &self.ball_body_handles {
-            let ball_body = self.rigid_body_set.get_mut(ball_body_handle.clone()).unwrap();
+        let force = vector![x, default_y, z];
+        if force == self.ball_force {
+            // nothing to do, and waking every ball would stop any from sleeping
+            return;
+        }
+        self.ball_force = force;
+        let wake_up = force != Vector::zeros();
+        for ball in &self.balls {
+            let ball_body = self.rigid_body_set.get_mut(ball.handle).unwrap();
 
-            ball_body.reset_forces(true);
-            // ball_body.enable_ccd(true);
-            // console_log!("{}", ball_body.is_ccd_active());
-            ball_body.add_force(vector![x, default_y, z], true);
+            ball_body.reset_forces(wake_up);
+            ball_body.add_force(force, wake_up);
+        }
+    }
+
+    // applies to every ball, and those spawned later
+    pub fn tune(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+        tuning.apply_to_parameters(&mut self.integration_parameters);
+        for ball in &self.balls {
+            tuning.apply_to_body(&mut self.rigid_body_set[ball.handle]);
+        }
+    }
+
+    pub fn tuning(&self) -> Tuning {
+        self.tuning
+    }
+
+    // records contacts of every ball, and those spawned later, with at least force_threshold
+    // force between them for ContactPhase::Force events, or stops recording them if None
+    pub fn record_contacts(&mut self, force_threshold: Option<Real>) {
+        self.contact_force_threshold = force_threshold;
+        for collider in self.ball_by_collider.keys() {
+            record_contacts_of(&mut self.collider_set[*collider], force_threshold);
+        }
+        if force_threshold.is_none() {
+            self.contacts.clear();
         }
     }
 
+    // contact events since the last drain, over however many steps, see ContactCollector::drain
+    pub fn drain_contacts(&mut self) -> Vec<ContactEvent> {
+        self.contacts.drain()
+    }
+
+    pub fn num_balls(&self) -> usize {
+        self.balls.len()
+    }
+
+    // balls which are asleep, and so not being simulated
+    pub fn num_sleeping_balls(&self) -> usize {
+        self.balls.iter().filter(|ball| self.rigid_body_set[ball.handle].is_sleeping()).count()
+    }
+
     pub fn ball_translations(&self) -> Vec<Vector<Real>> {
         let mut ball_translations = Vec::new();
-        for ball_body_handle in &self.ball_body_handles {
-            let ball_body = &self.rigid_body_set[ball_body_handle.clone()];
-            ball_translations.push(ball_body.translation().clone());
+        for ball in &self.balls {
+            let ball_body = &self.rigid_body_set[ball.handle];
+            ball_translations.push(*ball_body.translation());
         }
         ball_translations
     }
 
+    // translation and radius of each ball, in the same order as ball_translations,
+    // without collecting them
+    pub fn iter_balls(&self) -> impl Iterator<Item = (&Vector<Real>, Real)> + '_ {
+        self.balls.iter()
+            .map(|ball| (self.rigid_body_set[ball.handle].translation(), ball.radius))
+    }
+
+    // in the same order as ball_translations
+    pub fn ball_radii(&self) -> Vec<Real> {
+        self.balls.iter().map(|ball| ball.radius).collect()
+    }
+
+    // in the same order as ball_translations
+    pub fn ball_ids(&self) -> Vec<u32> {
+        self.balls.iter().map(|ball| ball.id).collect()
+    }
+
+    // of a standard ball
     pub fn ball_radius(&self) -> f32 {
         self.ball_radius
     }
 
+    // seconds simulated by each step
+    pub fn dt(&self) -> Real {
+        self.integration_parameters.dt
+    }
+
     pub fn step(&mut self) {
         let physics_hooks = ();
-        let event_handler = ();
 
         self.physics_pipeline.step(
             &self.gravity,
@@ -218,8 +427,374 @@ impl RapierState {
             &mut self.impulse_joint_set,
             &mut self.multibody_joint_set,
             &mut self.ccd_solver,
+            None,
             &physics_hooks,
-            &event_handler,
+            self.contacts.handler(),
         );
+        let (balls, despawned) = (&self.ball_by_collider, &self.despawned_by_collider);
+        self.contacts.end_step(|collider| balls.get(&collider).or_else(|| despawned.get(&collider)).copied(), 
+            &self.surroundings);
+        self.despawned_by_collider.clear();
+    }
+}
+
+fn record_contacts_of(collider: &mut Collider, force_threshold: Option<Real>) {
+    match force_threshold {
+        Some(force_threshold) => {
+            collider.set_active_events(ActiveEvents::COLLISION_EVENTS | ActiveEvents::CONTACT_FORCE_EVENTS);
+            collider.set_contact_force_event_threshold(force_threshold);
+        },
+        None => collider.set_active_events(ActiveEvents::empty())
+    }
+}
+
+#[cfg(test)]
+mod arena_tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    fn bumpy_terrain() -> Terrain {
+        Terrain::from_elevations(DMatrix::from_fn(20, 20, |row, column| {
+            ((row * 7 + column * 13) % 10) as Real
+        }))
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_same_seed_same_placement() {
+        let terrain = bumpy_terrain();
+        let first = Arena::new(Dimension { width: 10.0, depth: 10.0 }, 10, &terrain, 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        let second = Arena::new(Dimension { width: 10.0, depth: 10.0 }, 10, &terrain, 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        assert_eq!(first.physics.ball_translations(), second.physics.ball_translations());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_non_square_placement() {
+        let terrain = Terrain::from_elevations(DMatrix::from_fn(10, 40, |row, column| {
+            ((row * 7 + column * 13) % 10) as Real
+        }));
+        let dimension = Dimension { width: 40.0, depth: 10.0 };
+        let arena = Arena::new(dimension, 50, &terrain, 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        let radius = arena.physics.ball_radius();
+        let translations = arena.physics.ball_translations();
+        assert_eq!(50, translations.len());
+        for translation in translations {
+            assert!(translation.x >= radius && translation.x <= dimension.width - radius);
+            assert!(translation.z >= radius && translation.z <= dimension.depth - radius);
+        }
+        assert!(arena.physics.ball_translations().iter().any(|t| t.x > dimension.depth));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_normalised_height_scale() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let arena = Arena::new(dimension, 10, &terrain, 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        let radius = arena.physics.ball_radius();
+        assert_eq!(radius * 4.0, arena.height_scale.y_extent);
+        assert_eq!(radius * 4.0 / 9.0, arena.height_scale.units_per_metre);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_real_world_height_scale() {
+        use crate::tiles::{GeoBounds, Projection};
+
+        // 20 cells across ~1113m, with 9m between lowest and highest
+        let bounds = GeoBounds { west: 0.0, south: -0.005, east: 0.01, north: 0.005, projection: Projection::Equirectangular };
+        let terrain = bumpy_terrain().with_bounds(bounds);
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let real_world = Arena::new(dimension, 10, &terrain, 42, VerticalScale::RealWorld { exaggeration: 1.0 }, &Placement::low_biased(), &BallMix::default());
+        let units_per_metre = 10.0 / bounds.width_metres() as Real;
+        assert!((units_per_metre - real_world.height_scale.units_per_metre).abs() < 1e-6);
+        assert!((9.0 * units_per_metre - real_world.height_scale.y_extent).abs() < 1e-6);
+
+        let exaggerated = Arena::new(dimension, 10, &terrain, 42, VerticalScale::RealWorld { exaggeration: 3.0 }, &Placement::low_biased(), &BallMix::default());
+        assert!((3.0 * real_world.height_scale.y_extent - exaggerated.height_scale.y_extent).abs() < 1e-6);
+
+        // without bounds, there is no way to know the size in metres
+        let unknown = Arena::new(dimension, 10, &bumpy_terrain(), 42, VerticalScale::RealWorld { exaggeration: 1.0 }, &Placement::low_biased(), &BallMix::default());
+        let normalised = Arena::new(dimension, 10, &bumpy_terrain(), 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        assert_eq!(normalised.height_scale.y_extent, unknown.height_scale.y_extent);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_mixed_balls() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let mix = BallMix::new()
+            .with(BallKind::sand(), 1.0).unwrap()
+            .with(BallKind::boulder(), 1.0).unwrap();
+        let mut arena = Arena::new(dimension, 20, &terrain, 42, VerticalScale::Normalised, &Placement::uniform(), &mix);
+        let radius = arena.physics.ball_radius();
+
+        let radii = arena.physics.ball_radii();
+        assert_eq!(20, radii.len());
+        assert!(radii.contains(&(0.5 * radius)));
+        assert!(radii.contains(&(3.0 * radius)));
+        assert!(radii.iter().all(|r| *r == 0.5 * radius || *r == 3.0 * radius));
+
+        // boulders are much heavier than sand
+        let masses: Vec<Real> = arena.physics.balls.iter()
+            .map(|ball| arena.physics.rigid_body_set[ball.handle].mass())
+            .collect();
+        for (mass, r) in masses.iter().zip(radii.iter()) {
+            let expected_density = if *r == 3.0 * radius { 2.7 } else { 1.6 };
+            let volume = 4.0 / 3.0 * std::f32::consts::PI * r * r * r;
+            assert!((expected_density * volume - mass).abs() / mass < 0.001);
+        }
+
+        arena.physics.step();
+        assert_eq!(radii, arena.physics.ball_radii());
+        let (translations, iterated_radii): (Vec<Vector<Real>>, Vec<Real>) = arena.physics.iter_balls()
+            .map(|(translation, radius)| (*translation, radius))
+            .unzip();
+        assert_eq!(arena.physics.ball_translations(), translations);
+        assert_eq!(radii, iterated_radii);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_spawn_and_despawn() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let mut arena = Arena::new(dimension, 3, &terrain, 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        assert_eq!(vec![0, 1, 2], arena.physics.ball_ids());
+
+        let spawned = arena.physics.spawn_ball(vector![5.0, 10.0, 5.0], BallKind::boulder());
+        assert_eq!(3, spawned);
+        assert_eq!(vector![5.0, 10.0, 5.0], arena.physics.ball_translations()[3]);
+        assert_eq!(3.0 * arena.physics.ball_radius(), arena.physics.ball_radii()[3]);
+
+        assert!(arena.physics.despawn_ball(1));
+        assert!(!arena.physics.despawn_ball(1));
+        // ids are never reused, and the rest keep their order
+        assert_eq!(vec![0, 2, 3], arena.physics.ball_ids());
+        assert_eq!(4, arena.physics.spawn_ball(vector![2.0, 10.0, 2.0], BallKind::ball()));
+        arena.step();
+        assert_eq!(4, arena.physics.ball_translations().len());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_emitter() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let mut arena = Arena::new(dimension, 0, &terrain, 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        let emitter = arena.add_emitter(5.0, 5.0, 30.0, BallKind::sand());
+        // a second at 60 steps per second
+        for _ in 0..60 {
+            arena.step();
+        }
+        let poured = arena.physics.ball_ids().len();
+        assert!((28..=30).contains(&poured), "{}", poured);
+        for translation in arena.physics.ball_translations() {
+            assert!((translation.x - 5.0).abs() < 1.0 && (translation.z - 5.0).abs() < 1.0);
+        }
+
+        assert!(arena.remove_emitter(emitter));
+        assert!(!arena.remove_emitter(emitter));
+        for _ in 0..60 {
+            arena.step();
+        }
+        assert_eq!(poured, arena.physics.ball_ids().len());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_tuning() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let mut arena = Arena::new(dimension, 500, &terrain, 42, VerticalScale::Normalised, &Placement::uniform(), &BallMix::default());
+        // more balls than fit on the floor at once
+        assert_eq!(500, arena.physics.num_balls());
+        assert!(arena.physics.balls.iter().all(|ball| arena.physics.rigid_body_set[ball.handle].is_ccd_enabled()));
+
+        arena.physics.tune(Tuning::many_bodies());
+        arena.physics.spawn_ball(vector![5.0, 150.0, 5.0], BallKind::ball());
+        assert!(arena.physics.balls.iter().all(|ball| !arena.physics.rigid_body_set[ball.handle].is_ccd_enabled()));
+        assert_eq!(2, arena.physics.integration_parameters.max_velocity_iterations);
+
+        arena.physics.tune(Tuning::new(true, 4, false, 2.0));
+        assert!(arena.physics.balls.iter().all(|ball| arena.physics.rigid_body_set[ball.handle].is_ccd_enabled()));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_sleeping() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let mut arena = Arena::new(dimension, 0, &terrain, 42, VerticalScale::Normalised, &Placement::uniform(), &BallMix::default());
+        arena.physics.tune(Tuning::many_bodies());
+        for i in 0..16 {
+            let (x, z) = ((i % 4) as Real * 2.0 + 2.0, (i / 4) as Real * 2.0 + 2.0);
+            arena.physics.spawn_ball(vector![x, 0.5, z], BallKind::ball());
+        }
+
+        // balls settle, and then sleep, as long as the force doesn't change
+        for _ in 0..300 {
+            arena.physics.set_ball_force(0.0, 0.0);
+            arena.step();
+        }
+        assert!(arena.physics.num_sleeping_balls() > 8, "{}", arena.physics.num_sleeping_balls());
+
+        // until disturbed
+        arena.physics.set_ball_force(1.0, 0.0);
+        assert_eq!(0, arena.physics.num_sleeping_balls());
+
+        // or told not to
+        arena.physics.set_ball_force(0.0, 0.0);
+        for _ in 0..300 {
+            arena.step();
+        }
+        assert!(arena.physics.num_sleeping_balls() > 0);
+        arena.physics.tune(Tuning::new(false, 2, false, 0.5));
+        assert_eq!(0, arena.physics.num_sleeping_balls());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_contacts() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let mut arena = Arena::new(dimension, 0, &terrain, 42, VerticalScale::Normalised, &Placement::uniform(), &BallMix::default());
+        // one ball dropped onto another, and one rolled into a wall
+        let lower = arena.physics.spawn_ball(vector![5.0, 0.5, 5.0], BallKind::ball());
+        let upper = arena.physics.spawn_ball(vector![5.0, 1.0, 5.0], BallKind::ball());
+        let rolling = arena.physics.spawn_ball(vector![2.0, 0.5, 2.0], BallKind::ball());
+        arena.physics.rigid_body_set[arena.physics.balls[2].handle].set_linvel(vector![-5.0, 0.0, 0.0], true);
+
+        // nothing is recorded until asked for
+        for _ in 0..5 {
+            arena.step();
+        }
+        assert!(arena.physics.drain_contacts().is_empty());
+
+        arena.physics.record_contacts(Some(0.0));
+        let mut events = Vec::new();
+        for _ in 0..60 {
+            arena.step();
+            events.extend(arena.physics.drain_contacts());
+        }
+        let started = |ball: u32, with: ContactWith| events.iter()
+            .any(|event| event.phase == ContactPhase::Started && event.ball == ball && event.with == with);
+        assert!(started(lower, ContactWith::Terrain));
+        assert!(started(lower, ContactWith::Ball));
+        assert!(started(rolling, ContactWith::Wall));
+        assert!(events.iter().any(|event| event.with == ContactWith::Ball && event.ball == lower && event.other_ball == Some(upper)));
+        assert!(events.iter().any(|event| event.phase == ContactPhase::Force && event.force > 0.0));
+        assert!(events.iter().filter(|event| event.phase != ContactPhase::Force).all(|event| event.force == 0.0));
+
+        // and stops when no longer asked for
+        arena.physics.record_contacts(None);
+        arena.physics.set_ball_force(10.0, 10.0);
+        for _ in 0..10 {
+            arena.step();
+        }
+        assert!(arena.physics.drain_contacts().is_empty());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_contacts_over_several_steps() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let arena = || {
+            let mut arena = Arena::new(dimension, 0, &terrain, 42, VerticalScale::Normalised, &Placement::uniform(), &BallMix::default());
+            arena.physics.spawn_ball(vector![5.0, 0.5, 5.0], BallKind::ball());
+            arena.physics.spawn_ball(vector![5.0, 1.0, 5.0], BallKind::ball());
+            arena.physics.record_contacts(Some(0.0));
+            arena
+        };
+
+        // the same events in the same order, whether drained after every step or once after all of them
+        let mut every_step = arena();
+        let mut drained_every_step = Vec::new();
+        for _ in 0..60 {
+            every_step.step();
+            drained_every_step.extend(every_step.physics.drain_contacts());
+        }
+        let mut once = arena();
+        for _ in 0..60 {
+            once.step();
+        }
+        let drained_once = once.physics.drain_contacts();
+        assert_eq!(drained_every_step, drained_once);
+        // including contacts starting after forces from earlier steps
+        let first_force = drained_once.iter().position(|event| event.phase == ContactPhase::Force).unwrap();
+        assert!(drained_once[first_force..].iter().any(|event| event.phase == ContactPhase::Started));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_contacts_of_despawned_ball() {
+        let terrain = bumpy_terrain();
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let mut arena = Arena::new(dimension, 0, &terrain, 42, VerticalScale::Normalised, &Placement::uniform(), &BallMix::default());
+        let ball = arena.physics.spawn_ball(vector![5.0, 0.5, 5.0], BallKind::ball());
+        arena.physics.record_contacts(Some(0.0));
+        for _ in 0..60 {
+            arena.step();
+        }
+        arena.physics.drain_contacts();
+
+        // its contact with the terrain stops when it goes, during the next step
+        assert!(arena.physics.despawn_ball(ball));
+        arena.step();
+        let events = arena.physics.drain_contacts();
+        assert!(events.iter().any(|event| event.phase == ContactPhase::Stopped 
+            && event.ball == ball && event.with == ContactWith::Terrain), "{:?}", events);
+        arena.step();
+        assert!(arena.physics.drain_contacts().is_empty());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_snapshot_restore() {
+        let terrain = bumpy_terrain();
+        let mut original = Arena::new(Dimension { width: 10.0, depth: 10.0 }, 10, &terrain, 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        for _ in 0..10 {
+            original.physics.step();
+        }
+        let mut restored = Arena::restore(&original.snapshot()).unwrap();
+        assert_eq!(original.dimension.width, restored.dimension.width);
+        assert_eq!(original.dimension.depth, restored.dimension.depth);
+        assert_eq!(original.physics.ball_radius(), restored.physics.ball_radius());
+        assert_eq!(original.physics.ball_translations(), restored.physics.ball_translations());
+        for _ in 0..10 {
+            original.physics.step();
+            restored.physics.step();
+        }
+        assert_eq!(original.physics.ball_translations(), restored.physics.ball_translations());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_restore_emitters() {
+        let terrain = bumpy_terrain();
+        let mut original = Arena::new(Dimension { width: 10.0, depth: 10.0 }, 0, &terrain, 42, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        let snapshot = original.snapshot();
+        let emitter = original.add_emitter(5.0, 5.0, 30.0, BallKind::sand());
+
+        // a snapshot has no emitters, even if taken while pouring
+        let mut restored = Arena::restore(&original.snapshot()).unwrap();
+        for _ in 0..10 {
+            restored.step();
+        }
+        assert_eq!(0, restored.physics.num_balls());
+        assert!(!restored.remove_emitter(emitter));
+
+        // but can carry on with those of the arena it replaces
+        let mut restored = Arena::restore(&snapshot).unwrap();
+        restored.take_emitters_from(&mut original);
+        for _ in 0..10 {
+            restored.step();
+        }
+        assert!(restored.physics.num_balls() > 0);
+        assert_ne!(emitter, restored.add_emitter(1.0, 1.0, 1.0, BallKind::sand()));
+        assert!(restored.remove_emitter(emitter));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_restore_rejects_garbage() {
+        assert!(Arena::restore(&[1, 2, 3]).is_err());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_different_seed_different_placement() {
+        let terrain = bumpy_terrain();
+        let first = Arena::new(Dimension { width: 10.0, depth: 10.0 }, 10, &terrain, 1, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        let second = Arena::new(Dimension { width: 10.0, depth: 10.0 }, 10, &terrain, 2, VerticalScale::Normalised, &Placement::low_biased(), &BallMix::default());
+        assert_ne!(first.physics.ball_translations(), second.physics.ball_translations());
     }
 }
@@ -0,0 +1,120 @@
+// Contacts between balls and whatever they touch, collected from rapier's events
+// as the physics steps, until drained
+use rapier3d::crossbeam::channel::{unbounded, Receiver};
+use rapier3d::prelude::*;
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactPhase {
+    Started,
+    Stopped,
+    // while in contact, with more than the threshold force between them
+    Force
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactWith {
+    Ball,
+    Terrain,
+    Wall
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactEvent {
+    pub phase: ContactPhase,
+    pub ball: u32,
+    pub with: ContactWith,
+    // the id of the other ball when with a ball, which is always greater than ball
+    pub other_ball: Option<u32>,
+    // sum of the magnitudes of the forces at each contact point, for ContactPhase::Force
+    pub force: Real
+}
+
+// the colliders which aren't balls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Surroundings {
+    pub terrain: ColliderHandle,
+    pub walls: Vec<ColliderHandle>
+}
+
+pub struct ContactCollector {
+    collector: ChannelEventCollector,
+    collisions: Receiver<CollisionEvent>,
+    forces: Receiver<ContactForceEvent>,
+    // those of the steps since the last drain, step by step
+    events: Vec<ContactEvent>
+}
+
+impl Default for ContactCollector {
+    fn default() -> ContactCollector {
+        let (collision_sender, collisions) = unbounded();
+        let (force_sender, forces) = unbounded();
+        ContactCollector {
+            collector: ChannelEventCollector::new(collision_sender, force_sender),
+            collisions,
+            forces,
+            events: Vec::new()
+        }
+    }
+}
+
+impl ContactCollector {
+    pub fn handler(&self) -> &ChannelEventCollector {
+        &self.collector
+    }
+
+    // takes the events of the step just taken, as rapier only orders events within a step,
+    // leaving out any involving colliders which are neither balls, as given by ball, nor surroundings
+    pub fn end_step(&mut self, ball: impl Fn(ColliderHandle) -> Option<u32>, surroundings: &Surroundings) {
+        let collisions = self.collisions.try_iter().map(|event| {
+            let phase = if event.started() { ContactPhase::Started } else { ContactPhase::Stopped };
+            (phase, event.collider1(), event.collider2(), 0.0)
+        });
+        let forces = self.forces.try_iter().map(|event| {
+            (ContactPhase::Force, event.collider1, event.collider2, event.total_force_magnitude)
+        });
+        // rapier reports all collisions for a step before any forces
+        let events = collisions.chain(forces)
+            .filter_map(|(phase, collider1, collider2, force)| {
+                contact(&ball, surroundings, collider1, collider2)
+                    .map(|(ball, with, other_ball)| ContactEvent { phase, ball, with, other_ball, force })
+            });
+        self.events.extend(events);
+    }
+
+    // events of the steps since the last drain, in the order the steps were taken, and
+    // within a step, contacts starting or stopping before forces
+    pub fn drain(&mut self) -> Vec<ContactEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn clear(&mut self) {
+        self.collisions.try_iter().for_each(drop);
+        self.forces.try_iter().for_each(drop);
+        self.events.clear();
+    }
+}
+
+fn contact(ball: impl Fn(ColliderHandle) -> Option<u32>, surroundings: &Surroundings, collider1: ColliderHandle, collider2: ColliderHandle) -> Option<(u32, ContactWith, Option<u32>)> {
+    match (ball(collider1), ball(collider2)) {
+        (Some(ball1), Some(ball2)) => Some((ball1.min(ball2), ContactWith::Ball, Some(ball1.max(ball2)))),
+        (Some(ball), None) => with(surroundings, collider2).map(|with| (ball, with, None)),
+        (None, Some(ball)) => with(surroundings, collider1).map(|with| (ball, with, None)),
+        (None, None) => None
+    }
+}
+
+fn with(surroundings: &Surroundings, collider: ColliderHandle) -> Option<ContactWith> {
+    if collider == surroundings.terrain {
+        Some(ContactWith::Terrain)
+    } else if surroundings.walls.contains(&collider) {
+        Some(ContactWith::Wall)
+    } else {
+        None
+    }
+}
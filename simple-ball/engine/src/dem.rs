@@ -0,0 +1,190 @@
+// Parsers for digital elevation model (DEM) formats other than Terrain-RGB PNGs.
+// All return elevations stored as for Terrain, i.e.
+// x = columns, y = rows, where x, y is in screen space
+use std::io::Cursor;
+
+use rapier3d::prelude::*;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+use crate::tiles::{GeoBounds, Projection};
+
+const SRTM_VOID: i16 = -32768;
+// GeoTIFF's GTModelTypeGeoKey, and its value for longitude, latitude coordinates
+const MODEL_TYPE_GEO_KEY: u16 = 1024;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+
+pub struct Dem {
+    pub elevations: DMatrix<Real>,
+    // None unless the file says where it is, in longitude and latitude
+    pub bounds: Option<GeoBounds>
+}
+
+pub fn parse_geotiff(data: &[u8]) -> Result<Dem, String> {
+    let error = |e: tiff::TiffError| format!("could not read GeoTIFF: {}", e);
+
+    let mut decoder = Decoder::new(Cursor::new(data)).map_err(error)?;
+    let (width, height) = decoder.dimensions().map_err(error)?;
+    match decoder.colortype().map_err(error)? {
+        tiff::ColorType::Gray(_) => {},
+        other => return Err(format!("expected a single-band GeoTIFF but got {:?}", other))
+    }
+    // GDAL stores the no-data value as an ASCII tag
+    let no_data = match decoder.find_tag(Tag::GdalNodata).map_err(error)? {
+        Some(value) => value.into_string().ok().and_then(|s| s.trim_matches(char::from(0)).trim().parse::<f64>().ok()),
+        None => None
+    };
+    let bounds = geotiff_bounds(&mut decoder, width as usize, height as usize).map_err(error)?;
+    let values : Vec<f64> = match decoder.read_image().map_err(error)? {
+        DecodingResult::F32(values) => values.into_iter().map(f64::from).collect(),
+        DecodingResult::F64(values) => values,
+        DecodingResult::I16(values) => values.into_iter().map(f64::from).collect(),
+        DecodingResult::U16(values) => values.into_iter().map(f64::from).collect(),
+        DecodingResult::I32(values) => values.into_iter().map(f64::from).collect(),
+        DecodingResult::U8(values) => values.into_iter().map(f64::from).collect(),
+        _ => return Err("unsupported GeoTIFF sample format".to_string())
+    };
+    let values = values.into_iter().map(|value| {
+        match no_data {
+            Some(no_data) if value == no_data => None,
+            _ if value.is_nan() => None,
+            _ => Some(value as Real)
+        }
+    }).collect();
+    let elevations = from_row_major(height as usize, width as usize, values)?;
+    Ok(Dem { elevations, bounds })
+}
+
+// from the tiepoint of the top left pixel and the size of pixels, if in longitude and latitude
+fn geotiff_bounds(decoder: &mut Decoder<Cursor<&[u8]>>, width: usize, height: usize) -> tiff::TiffResult<Option<GeoBounds>> {
+    let tiepoint = decoder.find_tag(Tag::ModelTiepointTag)?.map(|value| value.into_f64_vec()).transpose()?;
+    let pixel_scale = decoder.find_tag(Tag::ModelPixelScaleTag)?.map(|value| value.into_f64_vec()).transpose()?;
+    let model_type = decoder.find_tag(Tag::GeoKeyDirectoryTag)?.map(|value| value.into_u16_vec()).transpose()?
+        .and_then(|keys| geo_key(&keys, MODEL_TYPE_GEO_KEY));
+    let (tiepoint, pixel_scale) = match (tiepoint, pixel_scale) {
+        (Some(tiepoint), Some(pixel_scale)) if tiepoint.len() >= 6 && pixel_scale.len() >= 2 => (tiepoint, pixel_scale),
+        _ => return Ok(None)
+    };
+    if model_type.is_some_and(|model_type| model_type != MODEL_TYPE_GEOGRAPHIC) {
+        return Ok(None);
+    }
+    // raster point i, j is at model point x, y
+    let (i, j, x, y) = (tiepoint[0], tiepoint[1], tiepoint[3], tiepoint[4]);
+    let (x_scale, y_scale) = (pixel_scale[0], pixel_scale[1]);
+    let west = x - i * x_scale;
+    let north = y + j * y_scale;
+    Ok(geographic_bounds(west, north - (height as f64) * y_scale, west + (width as f64) * x_scale, north))
+}
+
+// the value of a key held directly in a GeoKeyDirectoryTag, which is a header of
+// version, revision, minor revision and number of keys, then id, location, count, value of each key
+fn geo_key(keys: &[u16], id: u16) -> Option<u16> {
+    keys.get(4..)?.chunks_exact(4)
+        .find(|key| key[0] == id && key[1] == 0)
+        .map(|key| key[3])
+}
+
+pub fn parse_esri_ascii_grid(text: &str) -> Result<Dem, String> {
+    let mut tokens = text.split_whitespace().peekable();
+    let mut columns = None;
+    let mut rows = None;
+    let mut no_data = None;
+    // of the lower left cell's corner or centre
+    let mut x_lower_left = None;
+    let mut y_lower_left = None;
+    let mut lower_left_is_centre = false;
+    let mut cell_size = None;
+    // header is a series of "key value" lines, followed by the values
+    while let Some(key) = tokens.peek().filter(|t| t.parse::<f64>().is_err()) {
+        let key = key.to_lowercase();
+        tokens.next();
+        let value = tokens.next()
+            .ok_or_else(|| format!("missing value for '{}' in ESRI ASCII grid header", key))?;
+        let bad_value = || format!("bad value '{}' for '{}' in ESRI ASCII grid header", value, key);
+        match key.as_str() {
+            "ncols" => columns = Some(value.parse::<usize>().map_err(|_| bad_value())?),
+            "nrows" => rows = Some(value.parse::<usize>().map_err(|_| bad_value())?),
+            "nodata_value" => no_data = Some(value.parse::<f64>().map_err(|_| bad_value())?),
+            "xllcorner" | "xllcenter" => {
+                x_lower_left = Some(value.parse::<f64>().map_err(|_| bad_value())?);
+                lower_left_is_centre = key == "xllcenter";
+            },
+            "yllcorner" | "yllcenter" => y_lower_left = Some(value.parse::<f64>().map_err(|_| bad_value())?),
+            "cellsize" => cell_size = Some(value.parse::<f64>().map_err(|_| bad_value())?),
+            _ => {}
+        }
+    }
+    let columns = columns.ok_or("missing ncols in ESRI ASCII grid header")?;
+    let rows = rows.ok_or("missing nrows in ESRI ASCII grid header")?;
+    let values = tokens.map(|token| {
+        let value = token.parse::<f64>()
+            .map_err(|_| format!("bad value '{}' in ESRI ASCII grid", token))?;
+        Ok(if Some(value) == no_data { None } else { Some(value as Real) })
+    }).collect::<Result<Vec<Option<Real>>, String>>()?;
+    let elevations = from_row_major(rows, columns, values)?;
+    let bounds = match (x_lower_left, y_lower_left, cell_size) {
+        (Some(x), Some(y), Some(cell_size)) => {
+            let (west, south) = if lower_left_is_centre { (x - cell_size / 2.0, y - cell_size / 2.0) } else { (x, y) };
+            geographic_bounds(west, south, west + (columns as f64) * cell_size, south + (rows as f64) * cell_size)
+        },
+        _ => None
+    };
+    Ok(Dem { elevations, bounds })
+}
+
+// file_name is as SRTM tiles are named, e.g. N47E011.hgt for the tile whose south west
+// sample is at 47N 11E, without which the tile's bounds aren't known
+pub fn parse_srtm_hgt(data: &[u8], file_name: &str) -> Result<Dem, String> {
+    // square grid of big-endian i16, 1201x1201 for SRTM3 or 3601x3601 for SRTM1
+    let samples = data.len() / 2;
+    let side = (samples as f64).sqrt() as usize;
+    if !data.len().is_multiple_of(2) || side * side != samples {
+        return Err(format!("expected a square grid of 16-bit samples but got {} bytes", data.len()));
+    }
+    let values = data.chunks_exact(2).map(|bytes| {
+        let value = i16::from_be_bytes([bytes[0], bytes[1]]);
+        if value == SRTM_VOID { None } else { Some(value as Real) }
+    }).collect();
+    let elevations = from_row_major(side, side, values)?;
+    // samples are at the edges of the tile, as well as within it, so cells reach half a sample beyond
+    let bounds = srtm_tile_origin(file_name).filter(|_| side > 1).and_then(|(longitude, latitude)| {
+        let half_cell = 0.5 / ((side - 1) as f64);
+        geographic_bounds(longitude - half_cell, latitude - half_cell, longitude + 1.0 + half_cell, latitude + 1.0 + half_cell)
+    });
+    Ok(Dem { elevations, bounds })
+}
+
+// longitude, latitude of the south west corner of a tile named e.g. N47E011.hgt or s33w071.hgt
+pub fn srtm_tile_origin(file_name: &str) -> Option<(f64, f64)> {
+    let name = file_name.rsplit(['/', '\\']).next()?.to_uppercase();
+    let name = name.get(..7)?;
+    let sign = |hemisphere: char, positive: char, negative: char| match hemisphere {
+        h if h == positive => Some(1.0),
+        h if h == negative => Some(-1.0),
+        _ => None
+    };
+    let latitude = sign(name.chars().next()?, 'N', 'S')? * name.get(1..3)?.parse::<f64>().ok()?;
+    let longitude = sign(name.chars().nth(3)?, 'E', 'W')? * name.get(4..7)?.parse::<f64>().ok()?;
+    Some((longitude, latitude))
+}
+
+// None unless these are plausibly degrees of longitude and latitude, rather than e.g. metres.
+// DEM rows are evenly spaced in latitude
+fn geographic_bounds(west: f64, south: f64, east: f64, north: f64) -> Option<GeoBounds> {
+    let longitude = -180.0..=180.0;
+    let latitude = -90.0..=90.0;
+    let geographic = longitude.contains(&west) && longitude.contains(&east) && latitude.contains(&south) && latitude.contains(&north)
+        && west < east && south < north;
+    geographic.then_some(GeoBounds { west, south, east, north, projection: Projection::Equirectangular })
+}
+
+// no-data cells are filled with the lowest known elevation
+fn from_row_major(rows: usize, columns: usize, values: Vec<Option<Real>>) -> Result<DMatrix<Real>, String> {
+    if values.len() != rows * columns {
+        return Err(format!("expected {}x{} = {} values but got {}", columns, rows, rows * columns, values.len()));
+    }
+    let lowest = values.iter().flatten().cloned().reduce(Real::min)
+        .ok_or("no elevations found")?;
+    let values = values.into_iter().map(|value| value.unwrap_or(lowest));
+    Ok(DMatrix::from_row_iterator(rows, columns, values))
+}
@@ -1,28 +1,81 @@
 
 use nalgebra::Point2;
 use rapier3d::prelude::*;
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, Copy)]
+use crate::tiles::*;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Dimension {
-    pub side_length: f32
+    // x for an arena and screen
+    pub width: f32,
+    // z for an arena, y for a screen
+    pub depth: f32
+}
+
+impl Dimension {
+    // the largest dimension with the same aspect ratio as columns x rows,
+    // where neither side is longer than longest_side
+    pub fn with_aspect_ratio(longest_side: f32, columns: usize, rows: usize) -> Dimension {
+        let (columns, rows) = (columns as f32, rows as f32);
+        if columns >= rows {
+            Dimension { width: longest_side, depth: longest_side * rows / columns }
+        } else {
+            Dimension { width: longest_side * columns / rows, depth: longest_side }
+        }
+    }
 }
 
 pub fn map_screen_to_arena(screen: &Dimension, arena: &Dimension, point: Point2<Real>, default_y: Real) -> Vector<Real> {
-    let scale = arena.side_length / screen.side_length;
-    let x = point.x * scale;
-    let z = arena.side_length - (point.y * scale);
+    let x_scale = arena.width / screen.width;
+    let z_scale = arena.depth / screen.depth;
+    let x = point.x * x_scale;
+    let z = arena.depth - (point.y * z_scale);
     vector![x, default_y, z]
 }
 
 pub fn map_arena_to_screen(screen: &Dimension, arena: &Dimension, vector: Vector<Real>) -> Point2<Real> {
-    let scale = screen.side_length / arena.side_length;
-    let x = vector.x * scale;
-    let y = screen.side_length - (vector.z * scale);
+    let x_scale = screen.width / arena.width;
+    let y_scale = screen.depth / arena.depth;
+    let x = vector.x * x_scale;
+    let y = screen.depth - (vector.z * y_scale);
     Point2::new(x, y)
 }
 
+// the (row, column) of the cell of a terrain covering the arena which contains vector,
+// with points beyond the edges taken to be in the nearest edge cell
+pub fn map_arena_to_cell(arena: &Dimension, rows: usize, columns: usize, vector: Vector<Real>) -> (usize, usize) {
+    let column = ((vector.x / arena.width) * (columns as Real)).floor().max(0.0) as usize;
+    let row = (((arena.depth - vector.z) / arena.depth) * (rows as Real)).floor().max(0.0) as usize;
+    (row.min(rows - 1), column.min(columns - 1))
+}
+
+// assumes the arena covers the bounds, in the bounds' projection
+pub fn map_geo_to_arena(bounds: &GeoBounds, arena: &Dimension, longitude: f64, latitude: f64, default_y: Real) -> Vector<Real> {
+    let projection = bounds.projection;
+    let x_proportion = (longitude - bounds.west) / (bounds.east - bounds.west);
+    let north = projection.latitude_to_y(bounds.north);
+    let south = projection.latitude_to_y(bounds.south);
+    let z_proportion = (projection.latitude_to_y(latitude) - south) / (north - south);
+    let x = (x_proportion as Real) * arena.width;
+    let z = (z_proportion as Real) * arena.depth;
+    vector![x, default_y, z]
+}
+
+// returns longitude as x and latitude as y
+pub fn map_arena_to_geo(bounds: &GeoBounds, arena: &Dimension, vector: Vector<Real>) -> Point2<f64> {
+    let x_proportion = (vector.x / arena.width) as f64;
+    let z_proportion = (vector.z / arena.depth) as f64;
+    let projection = bounds.projection;
+    let north = projection.latitude_to_y(bounds.north);
+    let south = projection.latitude_to_y(bounds.south);
+    let longitude = bounds.west + x_proportion * (bounds.east - bounds.west);
+    let latitude = projection.y_to_latitude(south + z_proportion * (north - south));
+    Point2::new(longitude, latitude)
+}
 
 #[cfg(test)]
 mod mapping_tests {
@@ -38,10 +91,12 @@ mod mapping_tests {
 
     fn context() -> Context {
         let arena_dimension = Dimension {
-            side_length: 10.0
+            width: 10.0,
+            depth: 10.0
         };
         let screen_dimension = Dimension {
-            side_length: 100.0
+            width: 100.0,
+            depth: 100.0
         };
         let default_y = 0.123;
         let mappings = vec![
@@ -54,7 +109,7 @@ mod mapping_tests {
         }
     }
 
-    #[wasm_bindgen_test]
+    #[wasm_bindgen_test(unsupported = test)]
     fn test_map_screen_to_arena() {
         let context = context();
         for mapping in &context.mappings {
@@ -65,7 +120,87 @@ mod mapping_tests {
         }
     }
 
-    #[wasm_bindgen_test]
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_map_geo_to_arena_and_back() {
+        let context = context();
+        let bounds = GeoBounds {
+            west: tile_x_to_longitude(10, 500),
+            south: tile_y_to_latitude(10, 321),
+            east: tile_x_to_longitude(10, 501),
+            north: tile_y_to_latitude(10, 320),
+            projection: Projection::WebMercator
+        };
+        let width = context.arena_dimension.width;
+        let depth = context.arena_dimension.depth;
+        let corners = vec![
+            (bounds.west, bounds.south, vector![0.0, context.default_y, 0.0]),
+            (bounds.east, bounds.north, vector![width, context.default_y, depth]),
+            (bounds.west, bounds.north, vector![0.0, context.default_y, depth])
+        ];
+        for (longitude, latitude, expected) in corners {
+            let actual = map_geo_to_arena(&bounds, &context.arena_dimension, longitude, latitude, context.default_y);
+            assert!((expected - actual).norm() < 0.0001, "expected {} but was {}", expected, actual);
+        }
+
+        // the middle of a tile in mercator is not the middle in latitude
+        let middle = vector![width / 2.0, context.default_y, depth / 2.0];
+        let geo = map_arena_to_geo(&bounds, &context.arena_dimension, middle);
+        assert_eq!(bounds.west + (bounds.east - bounds.west) / 2.0, geo.x);
+        assert_ne!(bounds.south + (bounds.north - bounds.south) / 2.0, geo.y);
+        let actual = map_geo_to_arena(&bounds, &context.arena_dimension, geo.x, geo.y, context.default_y);
+        assert!((middle - actual).norm() < 0.0001, "expected {} but was {}", middle, actual);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_map_equirectangular_geo_to_arena_and_back() {
+        let context = context();
+        let bounds = GeoBounds { west: -71.0, south: -33.0, east: -70.0, north: -32.0, projection: Projection::Equirectangular };
+        let (width, depth) = (context.arena_dimension.width, context.arena_dimension.depth);
+
+        // the middle of the bounds in latitude is the middle of the arena
+        let middle = vector![width / 2.0, context.default_y, depth / 2.0];
+        let actual = map_geo_to_arena(&bounds, &context.arena_dimension, -70.5, -32.5, context.default_y);
+        assert!((middle - actual).norm() < 0.0001, "expected {} but was {}", middle, actual);
+        let quarter = map_arena_to_geo(&bounds, &context.arena_dimension, vector![width / 4.0, context.default_y, depth / 4.0]);
+        assert!((quarter.x - -70.75).abs() < 1e-6);
+        assert!((quarter.y - -32.75).abs() < 1e-6);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_non_square_mapping() {
+        let arena = Dimension { width: 20.0, depth: 10.0 };
+        let screen = Dimension { width: 200.0, depth: 50.0 };
+        let default_y = 0.123;
+        let mappings = vec![
+            (Point2::new(0.0, 0.0), vector![0.0, default_y, 10.0]),
+            (Point2::new(200.0, 50.0), vector![20.0, default_y, 0.0]),
+            (Point2::new(50.0, 10.0), vector![5.0, default_y, 8.0])
+        ];
+        for (point, vector) in mappings {
+            assert_eq!(vector, map_screen_to_arena(&screen, &arena, point, default_y));
+            assert_eq!(point, map_arena_to_screen(&screen, &arena, vector));
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_map_arena_to_cell() {
+        let arena = Dimension { width: 20.0, depth: 10.0 };
+        // row 0 is at the back of the arena, i.e. the top of the screen
+        assert_eq!((0, 0), map_arena_to_cell(&arena, 5, 4, vector![0.0, 0.0, 10.0]));
+        assert_eq!((4, 3), map_arena_to_cell(&arena, 5, 4, vector![19.9, 0.0, 0.1]));
+        assert_eq!((2, 1), map_arena_to_cell(&arena, 5, 4, vector![5.0, 0.0, 5.0]));
+        assert_eq!((4, 3), map_arena_to_cell(&arena, 5, 4, vector![25.0, 0.0, -1.0]));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_with_aspect_ratio() {
+        let wide = Dimension::with_aspect_ratio(50.0, 200, 100);
+        assert_eq!((50.0, 25.0), (wide.width, wide.depth));
+        let tall = Dimension::with_aspect_ratio(50.0, 100, 400);
+        assert_eq!((12.5, 50.0), (tall.width, tall.depth));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
     fn test_map_arena_to_screen() {
         let context = context();
         for mapping in &context.mappings {
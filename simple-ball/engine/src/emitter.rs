@@ -0,0 +1,63 @@
+// Pours balls onto the terrain at a steady rate, from above a point
+use rapier3d::prelude::*;
+
+use crate::arena::*;
+use crate::dimension::*;
+use crate::material::*;
+
+// balls are dropped at points spiralling out from the centre, so that those
+// poured in quick succession don't land on each other
+const SPIRAL_POINTS: u32 = 16;
+// in radii of the balls being poured
+const SPIRAL_RADIUS: Real = 8.0;
+const GOLDEN_ANGLE: Real = 2.399_963;
+
+#[derive(Debug, Clone)]
+pub struct Emitter {
+    pub id: u32,
+    // arena x, z of the centre of the pour
+    pub x: Real,
+    pub z: Real,
+    pub balls_per_second: Real,
+    pub kind: BallKind,
+    // balls due but not yet poured, including part of the next ball
+    owed: Real,
+    // how far around the spiral to drop the next ball
+    next_point: u32
+}
+
+impl Emitter {
+    pub fn new(id: u32, x: Real, z: Real, balls_per_second: Real, kind: BallKind) -> Emitter {
+        Emitter { id, x, z, balls_per_second, kind, owed: 0.0, next_point: 0 }
+    }
+
+    // adds the balls due after another dt seconds, dropped from height y, returning how many.
+    // A ball which would overlap another is held back until there is room, but no more than
+    // a second's worth are held back, so that the pour doesn't burst out once clear
+    pub fn emit(&mut self, physics: &mut RapierState, dimension: &Dimension, y: Real, dt: Real) -> usize {
+        self.owed = (self.owed + self.balls_per_second * dt).min(self.balls_per_second.max(1.0));
+        let radius = self.kind.radius * physics.ball_radius();
+        let mut emitted = 0;
+        while self.owed >= 1.0 {
+            let translation = self.next_translation(radius, dimension, y);
+            if !physics.is_clear(&translation, radius) {
+                break;
+            }
+            physics.spawn_ball(translation, self.kind);
+            self.owed -= 1.0;
+            emitted += 1;
+        }
+        emitted
+    }
+
+    // the next point around the spiral, kept within the walls
+    fn next_translation(&mut self, radius: Real, dimension: &Dimension, y: Real) -> Vector<Real> {
+        let point = self.next_point;
+        self.next_point = (self.next_point + 1) % SPIRAL_POINTS;
+        let angle = point as Real * GOLDEN_ANGLE;
+        let distance = SPIRAL_RADIUS * radius * ((point as Real + 0.5) / SPIRAL_POINTS as Real).sqrt();
+        let x = (self.x + distance * angle.cos()).clamp(radius, (dimension.width - radius).max(radius));
+        let z = (self.z + distance * angle.sin()).clamp(radius, (dimension.depth - radius).max(radius));
+        vector![x, y, z]
+    }
+}
@@ -0,0 +1,39 @@
+use rapier3d::prelude::*;
+use image::{DynamicImage, ImageBuffer};
+
+// values are expected to be stored as for Terrain elevations, i.e.
+// x = columns, y = rows, where x, y is in screen space
+pub fn as_grayscale_image(values: &DMatrix<Real>) -> Vec<u8> {
+    use std::io::Cursor;
+
+    let min = values.min();
+    let max = values.max();
+    let range = max - min;
+    let max_luma = u16::MAX as f32;
+    let scale = if range > 0.0 { max_luma / range } else { 0.0 };
+    let offset = min;
+
+    let image_buffer
+        = ImageBuffer::from_fn(values.ncols() as u32, values.nrows() as u32, |x, y| {
+        let row = y as usize;
+        let column = x as usize;
+        let value = values.index((row, column));
+        let luma = ((value - offset) * scale) as u16;
+        image::Luma([luma])
+    });
+
+    let image = DynamicImage::ImageLuma16(image_buffer);
+
+    log::debug!("writing image");
+    let mut cursor = Cursor::new(Vec::new());
+    image::write_buffer_with_format(
+        &mut cursor,
+        image.as_bytes(),
+        image.width(),
+        image.height(),
+        image.color(),
+        image::ImageFormat::Png
+    ).unwrap();
+    log::debug!("wrote image");
+    cursor.get_ref().clone()
+}
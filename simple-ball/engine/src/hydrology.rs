@@ -0,0 +1,467 @@
+// Drainage of water over elevations, which are expected to be stored as for
+// Terrain, i.e. x = columns, y = rows, where x, y is in screen space, so that
+// row 0 is north. Water does not drain off the edges
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+
+use rapier3d::prelude::*;
+
+// direction of cells which have no lower neighbour to flow to
+pub const NO_FLOW: Real = -1.0;
+
+// neighbours clockwise from north, as (row, column) offsets, so that
+// neighbour i is 45 * i degrees clockwise from north
+const NEIGHBOURS: [(isize, isize); 8] = [(-1, 0), (-1, 1), (0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1)];
+
+// triangular facets between a cardinal and diagonal neighbour, as in Tarboton (1997)
+const FACETS: [(usize, usize); 8] = [(0, 1), (2, 1), (2, 3), (4, 3), (4, 5), (6, 5), (6, 7), (0, 7)];
+
+fn neighbour(elevations: &DMatrix<Real>, row: usize, column: usize, index: usize) -> Option<(usize, usize)> {
+    let (row_offset, column_offset) = NEIGHBOURS[index];
+    let row = row.checked_add_signed(row_offset).filter(|r| *r < elevations.nrows())?;
+    let column = column.checked_add_signed(column_offset).filter(|c| *c < elevations.ncols())?;
+    Some((row, column))
+}
+
+fn distance(index: usize, cell_width: Real, cell_height: Real) -> Real {
+    match NEIGHBOURS[index] {
+        (0, _) => cell_width,
+        (_, 0) => cell_height,
+        _ => cell_width.hypot(cell_height)
+    }
+}
+
+// the steepest downhill neighbour, if any
+fn d8_receiver(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real, row: usize, column: usize) -> Option<usize> {
+    let elevation = elevations[(row, column)];
+    let mut steepest = None;
+    let mut steepest_slope = 0.0;
+    for index in 0..NEIGHBOURS.len() {
+        if let Some(cell) = neighbour(elevations, row, column, index) {
+            let slope = (elevation - elevations[cell]) / distance(index, cell_width, cell_height);
+            if slope > steepest_slope {
+                steepest = Some(index);
+                steepest_slope = slope;
+            }
+        }
+    }
+    steepest
+}
+
+// direction of steepest downhill flow, as (degrees clockwise from north,
+// [(neighbour, proportion of flow)]), if any
+fn dinf_receivers(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real, row: usize, column: usize) -> Option<(Real, [(usize, Real); 2])> {
+    let elevation = elevations[(row, column)];
+    let mut steepest = None;
+    let mut steepest_slope = 0.0;
+    for (cardinal, diagonal) in FACETS {
+        let Some(cardinal_cell) = neighbour(elevations, row, column, cardinal) else {
+            continue
+        };
+        // distances to the cardinal neighbour, and from it to the diagonal one
+        let (d1, d2) = if cardinal % 4 == 0 { (cell_height, cell_width) } else { (cell_width, cell_height) };
+        let s1 = (elevation - elevations[cardinal_cell]) / d1;
+        let max_angle = d2.atan2(d1);
+        // angle from the cardinal towards the diagonal, which along an edge
+        // can only be along the edge
+        let (angle, slope) = match neighbour(elevations, row, column, diagonal) {
+            None => (0.0, s1),
+            Some(diagonal_cell) => {
+                let s2 = (elevations[cardinal_cell] - elevations[diagonal_cell]) / d2;
+                match s2.atan2(s1) {
+                    angle if angle < 0.0 => (0.0, s1),
+                    angle if angle > max_angle => (max_angle, (elevation - elevations[diagonal_cell]) / d1.hypot(d2)),
+                    angle => (angle, s1.hypot(s2))
+                }
+            }
+        };
+        if slope > steepest_slope {
+            let clockwise = if diagonal == (cardinal + 1) % 8 { 1.0 } else { -1.0 };
+            let direction = (cardinal as Real * 45.0 + clockwise * angle.to_degrees()).rem_euclid(360.0);
+            let diagonal_proportion = angle / max_angle;
+            steepest = Some((direction, [(cardinal, 1.0 - diagonal_proportion), (diagonal, diagonal_proportion)]));
+            steepest_slope = slope;
+        }
+    }
+    steepest
+}
+
+// in degrees clockwise from north, towards one of the 8 neighbours, or NO_FLOW
+pub fn d8_flow_direction(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    DMatrix::from_fn(elevations.nrows(), elevations.ncols(), |row, column| {
+        match d8_receiver(elevations, cell_width, cell_height, row, column) {
+            Some(index) => index as Real * 45.0,
+            None => NO_FLOW
+        }
+    })
+}
+
+// in degrees clockwise from north, at any angle, or NO_FLOW
+pub fn dinf_flow_direction(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    DMatrix::from_fn(elevations.nrows(), elevations.ncols(), |row, column| {
+        match dinf_receivers(elevations, cell_width, cell_height, row, column) {
+            Some((direction, _)) => direction,
+            None => NO_FLOW
+        }
+    })
+}
+
+// cells from highest to lowest, so that every cell comes before those it drains into
+fn highest_first(elevations: &DMatrix<Real>) -> Vec<(usize, usize)> {
+    let mut cells: Vec<(usize, usize)> = (0..elevations.nrows())
+        .flat_map(|row| (0..elevations.ncols()).map(move |column| (row, column)))
+        .collect();
+    cells.sort_by(|a, b| elevations[*b].total_cmp(&elevations[*a]));
+    cells
+}
+
+// number of cells, including itself, which drain through each cell
+pub fn d8_flow_accumulation(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    let mut accumulation = DMatrix::from_element(elevations.nrows(), elevations.ncols(), 1.0);
+    for (row, column) in highest_first(elevations) {
+        if let Some(index) = d8_receiver(elevations, cell_width, cell_height, row, column) {
+            let cell = neighbour(elevations, row, column, index).unwrap();
+            accumulation[cell] += accumulation[(row, column)];
+        }
+    }
+    accumulation
+}
+
+// as for d8_flow_accumulation, but with flow split between two neighbours
+pub fn dinf_flow_accumulation(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    let mut accumulation = DMatrix::from_element(elevations.nrows(), elevations.ncols(), 1.0);
+    for (row, column) in highest_first(elevations) {
+        if let Some((_, receivers)) = dinf_receivers(elevations, cell_width, cell_height, row, column) {
+            let flow = accumulation[(row, column)];
+            for (index, proportion) in receivers {
+                if proportion > 0.0 {
+                    let cell = neighbour(elevations, row, column, index).unwrap();
+                    accumulation[cell] += flow * proportion;
+                }
+            }
+        }
+    }
+    accumulation
+}
+
+// a label per catchment, numbered from 1 in the order their lowest points are first
+// found, row by row. A catchment is every cell which drains, following D8 directions,
+// to the same pit or to the same flat area with no way out
+pub fn d8_catchments(elevations: &DMatrix<Real>, cell_width: Real, cell_height: Real) -> DMatrix<Real> {
+    let (rows, columns) = elevations.shape();
+    let mut receivers: DMatrix<Option<(usize, usize)>> = DMatrix::from_fn(rows, columns, |row, column| {
+        d8_receiver(elevations, cell_width, cell_height, row, column)
+            .map(|index| neighbour(elevations, row, column, index).unwrap())
+    });
+
+    // cells with nowhere to go either share a label with the rest of their flat area,
+    // or, where the flat area has ways out, drain through whichever is nearest
+    let mut catchments = DMatrix::zeros(rows, columns);
+    let mut next_catchment = 1.0;
+    for row in 0..rows {
+        for column in 0..columns {
+            if receivers[(row, column)].is_some() || catchments[(row, column)] != 0.0 {
+                continue;
+            }
+            let elevation = elevations[(row, column)];
+            let mut flat = HashSet::from([(row, column)]);
+            let mut visited = flat.clone();
+            let mut ways_out = VecDeque::new();
+            let mut unexplored = VecDeque::from([(row, column)]);
+            while let Some((row, column)) = unexplored.pop_front() {
+                for index in 0..NEIGHBOURS.len() {
+                    if let Some(cell) = neighbour(elevations, row, column, index) {
+                        if elevations[cell] != elevation || !visited.insert(cell) {
+                            continue;
+                        }
+                        if receivers[cell].is_some() {
+                            ways_out.push_back(cell);
+                        } else {
+                            flat.insert(cell);
+                            unexplored.push_back(cell);
+                        }
+                    }
+                }
+            }
+            if ways_out.is_empty() {
+                for cell in flat {
+                    catchments[cell] = next_catchment;
+                }
+                next_catchment += 1.0;
+            } else {
+                // spread back from the ways out, so each cell drains towards the nearest
+                while let Some((row, column)) = ways_out.pop_front() {
+                    for index in 0..NEIGHBOURS.len() {
+                        if let Some(cell) = neighbour(elevations, row, column, index) {
+                            if flat.remove(&cell) {
+                                receivers[cell] = Some((row, column));
+                                ways_out.push_back(cell);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // every other cell takes the label of wherever it drains to
+    for row in 0..rows {
+        for column in 0..columns {
+            let mut path = vec![];
+            let mut cell = (row, column);
+            while catchments[cell] == 0.0 {
+                path.push(cell);
+                cell = receivers[cell].unwrap();
+            }
+            let catchment = catchments[cell];
+            for cell in path {
+                catchments[cell] = catchment;
+            }
+        }
+    }
+    catchments
+}
+
+// a cell waiting to be visited, ordered so that the lowest is visited first
+#[derive(PartialEq)]
+struct Lowest(Real, (usize, usize));
+
+impl Eq for Lowest {}
+
+impl Ord for Lowest {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.total_cmp(&self.0).then_with(|| other.1.cmp(&self.1))
+    }
+}
+
+impl PartialOrd for Lowest {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// raises every cell which has no downhill path to an edge up to the level at
+// which it would spill over, as in the Priority-Flood of Barnes et al. (2014).
+// Unlike for flow, the edges are taken to be where water leaves the terrain
+pub fn fill_depressions(elevations: &DMatrix<Real>) -> DMatrix<Real> {
+    let (rows, columns) = elevations.shape();
+    let mut filled = elevations.clone();
+    let mut visited = DMatrix::from_element(rows, columns, false);
+    let mut queue = BinaryHeap::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            if row == 0 || column == 0 || row == rows - 1 || column == columns - 1 {
+                visited[(row, column)] = true;
+                queue.push(Lowest(elevations[(row, column)], (row, column)));
+            }
+        }
+    }
+    while let Some(Lowest(level, (row, column))) = queue.pop() {
+        for index in 0..NEIGHBOURS.len() {
+            if let Some(cell) = neighbour(elevations, row, column, index) {
+                if !visited[cell] {
+                    visited[cell] = true;
+                    filled[cell] = filled[cell].max(level);
+                    queue.push(Lowest(filled[cell], cell));
+                }
+            }
+        }
+    }
+    filled
+}
+
+// for each cell, a label shared with the cells of the same depression, numbered
+// from 1 in the order they are first found, row by row, or 0 outside any depression
+pub fn depression_basins(elevations: &DMatrix<Real>, filled: &DMatrix<Real>) -> DMatrix<Real> {
+    let (rows, columns) = elevations.shape();
+    let mut basins = DMatrix::zeros(rows, columns);
+    let mut next_basin = 1.0;
+    for row in 0..rows {
+        for column in 0..columns {
+            if filled[(row, column)] <= elevations[(row, column)] || basins[(row, column)] != 0.0 {
+                continue;
+            }
+            basins[(row, column)] = next_basin;
+            let mut unexplored = VecDeque::from([(row, column)]);
+            while let Some((row, column)) = unexplored.pop_front() {
+                for index in 0..NEIGHBOURS.len() {
+                    if let Some(cell) = neighbour(elevations, row, column, index) {
+                        if filled[cell] > elevations[cell] && basins[cell] == 0.0 {
+                            basins[cell] = next_basin;
+                            unexplored.push_back(cell);
+                        }
+                    }
+                }
+            }
+            next_basin += 1.0;
+        }
+    }
+    basins
+}
+
+// fills only those depressions which are shallower than min_depth
+pub fn fill_shallow_depressions(elevations: &DMatrix<Real>, min_depth: Real) -> DMatrix<Real> {
+    let filled = fill_depressions(elevations);
+    let basins = depression_basins(elevations, &filled);
+    let depths = &filled - elevations;
+    let basin_count = basins.max() as usize;
+    let mut deepest = vec![0.0; basin_count + 1];
+    for (basin, depth) in basins.iter().zip(depths.iter()) {
+        let basin = *basin as usize;
+        deepest[basin] = depth.max(deepest[basin]);
+    }
+    DMatrix::from_fn(elevations.nrows(), elevations.ncols(), |row, column| {
+        let basin = basins[(row, column)] as usize;
+        if basin != 0 && deepest[basin] < min_depth {
+            filled[(row, column)]
+        } else {
+            elevations[(row, column)]
+        }
+    })
+}
+
+#[cfg(test)]
+mod hydrology_tests {
+    use nalgebra::dmatrix;
+    use std::f32::consts::FRAC_PI_4;
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    fn assert_near(expected: Real, actual: Real) {
+        assert!((expected - actual).abs() < 0.001, "expected {} but was {}", expected, actual);
+    }
+
+    // falls by 2 per cell eastwards and by 1 per cell southwards
+    fn plane() -> DMatrix<Real> {
+        DMatrix::from_fn(3, 3, |row, column| -(2.0 * column as Real + row as Real))
+    }
+
+    // two pits, one 1 deep on the left and one 3 deep on the right,
+    // which spills over the 3 towards the corner
+    fn pitted() -> DMatrix<Real> {
+        dmatrix![
+            5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0;
+            5.0, 4.0, 5.0, 5.0, 0.0, 0.0, 5.0;
+            5.0, 5.0, 5.0, 5.0, 0.0, 3.0, 5.0;
+            5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 0.0
+        ]
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_fill_depressions() {
+        let expected = dmatrix![
+            5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0;
+            5.0, 5.0, 5.0, 5.0, 3.0, 3.0, 5.0;
+            5.0, 5.0, 5.0, 5.0, 3.0, 3.0, 5.0;
+            5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 0.0
+        ];
+        assert_eq!(expected, fill_depressions(&pitted()));
+        // filling again changes nothing
+        assert_eq!(expected, fill_depressions(&expected));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_depression_basins() {
+        let elevations = pitted();
+        let basins = depression_basins(&elevations, &fill_depressions(&elevations));
+        let expected = dmatrix![
+            0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0;
+            0.0, 1.0, 0.0, 0.0, 2.0, 2.0, 0.0;
+            0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0;
+            0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0
+        ];
+        assert_eq!(expected, basins);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_fill_shallow_depressions() {
+        let elevations = pitted();
+        let filled = fill_shallow_depressions(&elevations, 2.0);
+        // the shallow pit is filled, but the deep one is kept
+        assert_eq!(5.0, filled[(1, 1)]);
+        assert_eq!(elevations.view((1, 4), (2, 2)).into_owned(), filled.view((1, 4), (2, 2)).into_owned());
+        assert_eq!(fill_depressions(&elevations), fill_shallow_depressions(&elevations, 10.0));
+        assert_eq!(elevations, fill_shallow_depressions(&elevations, 0.0));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_d8_catchments() {
+        // the pits each collect their own surroundings, and the corner a little
+        let catchments = d8_catchments(&pitted(), 1.0, 1.0);
+        let expected = dmatrix![
+            1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0;
+            1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0;
+            1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 3.0;
+            1.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0
+        ];
+        assert_eq!(expected, catchments);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_d8_catchments_of_flats() {
+        // a flat valley floor with no way out is one catchment
+        let valley = dmatrix![
+            3.0, 1.0, 1.0, 3.0;
+            3.0, 1.0, 1.0, 3.0
+        ];
+        assert_eq!(DMatrix::from_element(2, 4, 1.0), d8_catchments(&valley, 1.0, 1.0));
+
+        // but a flat plateau drains towards whichever edge is nearest
+        let plateau = dmatrix![
+            5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0;
+            0.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 1.0;
+            5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0, 5.0
+        ];
+        let catchments = d8_catchments(&plateau, 1.0, 1.0);
+        let expected = dmatrix![
+            1.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0;
+            1.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0;
+            1.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0
+        ];
+        assert_eq!(expected, catchments);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_d8_flow_direction() {
+        let directions = d8_flow_direction(&plane(), 1.0, 1.0);
+        // a drop of 3 diagonally beats 2 eastwards
+        assert_eq!(135.0, directions[(0, 0)]);
+        assert_eq!(180.0, directions[(0, 2)]);
+        assert_eq!(90.0, directions[(2, 0)]);
+        assert_eq!(NO_FLOW, directions[(2, 2)]);
+        // but not when cells are much taller than they are wide
+        assert_eq!(90.0, d8_flow_direction(&plane(), 1.0, 10.0)[(0, 0)]);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_dinf_flow_direction() {
+        let directions = dinf_flow_direction(&plane(), 1.0, 1.0);
+        // straight down the plane's gradient
+        let expected = (2.0 as Real).atan2(-1.0).to_degrees();
+        assert_near(expected, directions[(0, 0)]);
+        assert_near(expected, directions[(1, 1)]);
+        assert_eq!(NO_FLOW, directions[(2, 2)]);
+        assert_eq!(NO_FLOW, dinf_flow_direction(&DMatrix::from_element(2, 2, 1.0), 1.0, 1.0)[(0, 0)]);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_flow_accumulation_along_a_line() {
+        let line = dmatrix![3.0, 2.0, 1.0, 0.0];
+        let expected = dmatrix![1.0, 2.0, 3.0, 4.0];
+        assert_eq!(expected, d8_flow_accumulation(&line, 1.0, 1.0));
+        assert_eq!(expected, dinf_flow_accumulation(&line, 1.0, 1.0));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_flow_accumulation_on_a_plane() {
+        let d8 = d8_flow_accumulation(&plane(), 1.0, 1.0);
+        assert_eq!(dmatrix![1.0, 1.0, 1.0; 1.0, 2.0, 3.0; 1.0, 3.0, 9.0], d8);
+
+        // flow from the top-left is split, but all of it still ends up in the bottom-right
+        let dinf = dinf_flow_accumulation(&plane(), 1.0, 1.0);
+        let to_diagonal = (0.5 as Real).atan() / FRAC_PI_4;
+        assert_near(1.0 + (1.0 - to_diagonal), dinf[(0, 1)]);
+        assert_near(9.0, dinf[(2, 2)]);
+    }
+}
@@ -1,61 +1,470 @@
-extern crate console_error_panic_hook;
-
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
+use nalgebra::Point2;
 use rapier3d::prelude::*;
 
-mod log;
-mod terrain;
-mod screen;
-mod dimension;
-mod arena;
+pub mod grayscale;
+pub mod dem;
+pub mod analysis;
+pub mod hydrology;
+pub mod resample;
+pub mod tiles;
+pub mod terrain;
+pub mod screen;
+pub mod dimension;
+pub mod placement;
+pub mod material;
+pub mod emitter;
+pub mod arena;
+pub mod water;
+pub mod shared;
+pub mod timestep;
+pub mod tuning;
+pub mod contacts;
 
-use log::*;
+use grayscale::*;
 use terrain::*;
 use screen::*;
 use dimension::*;
+use tiles::GeoBounds;
 use arena::*;
+use placement::Placement;
+use material::{BallKind, BallMix};
+use water::*;
+use timestep::FixedTimestep;
+use tuning::Tuning;
+use contacts::ContactEvent;
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 pub struct Simulation {
     screen: Screen,
-    arena: Arena
+    arena: Arena,
+    water: ShallowWater,
+    bounds: Option<GeoBounds>,
+    catchments: DMatrix<Real>,
+    timestep: FixedTimestep,
+    // screen x, y, radius of each ball, before and after the last step, and
+    // interpolated between them by how far the timestep is towards the next step
+    previous_ball_state: Vec<f32>,
+    current_ball_state: Vec<f32>,
+    ball_state: Vec<f32>,
+    // the catchment each ball is in, in the same order, after the last step
+    ball_catchments: Vec<u32>,
+    // depth of water in each cell, in metres, row by row, after the last step
+    water_state: Vec<f32>
 }
 
-#[wasm_bindgen]
+// steps to take at most in one update, see FixedTimestep
+const MAX_STEPS_PER_UPDATE: usize = 4;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Simulation {
-    #[wasm_bindgen(constructor)]
-    pub fn new(num_balls: u8, terrain: &Terrain, screen: &Screen) -> Simulation {
-        console_error_panic_hook::set_once();
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    // vertical_exaggeration of None squashes heights into a few ball radii, otherwise
+    // heights are in real-world proportion to the terrain's size, times the exaggeration
+    pub fn new(num_balls: u32, terrain: &Terrain, screen: &Screen, seed: u64, vertical_exaggeration: Option<f32>, placement: &Placement, mix: &BallMix) -> Simulation {
+        #[cfg(feature = "wasm")]
+        {
+            console_error_panic_hook::set_once();
+            let _ = console_log::init_with_level(log::Level::Info);
+        }
 
-        let arena = Arena::new(50.0, num_balls, terrain);
-        console_log!("Creating Simulation, with num_balls {:?}, using screen {:?}, terrain of {}x{}, and arena {:?}", 
-            num_balls, screen, terrain.width, terrain.height, arena.dimension);
-        Simulation { screen: screen.clone(), arena }
+        let arena_dimension = Dimension::with_aspect_ratio(50.0, terrain.width, terrain.height);
+        let vertical_scale = match vertical_exaggeration {
+            Some(exaggeration) => VerticalScale::RealWorld { exaggeration },
+            None => VerticalScale::Normalised
+        };
+        let arena = Arena::new(arena_dimension, num_balls, terrain, seed, vertical_scale, placement, mix);
+        // the water's bed is the terrain's elevations, in metres, so its cells are too
+        let (water_cell_size, _) = terrain.cell_size();
+        let water = ShallowWater::new(terrain, water_cell_size);
+        log::info!("Creating Simulation, with num_balls {:?}, seed {}, {:?}, using screen {:?}, terrain of {}x{}, and arena {:?} with {:?}", 
+            num_balls, seed, placement, screen, terrain.width, terrain.height, arena.dimension, arena.height_scale);
+        let catchments = terrain.catchments();
+        let timestep = FixedTimestep::new(arena.physics.dt(), MAX_STEPS_PER_UPDATE);
+        let mut simulation = Simulation { 
+            screen: screen.clone(), arena, water, bounds: terrain.bounds(), catchments, timestep, 
+            previous_ball_state: Vec::new(), current_ball_state: Vec::new(), ball_state: Vec::new(), ball_catchments: Vec::new(), water_state: Vec::new() 
+        };
+        simulation.refresh_ball_state();
+        simulation.refresh_water_state();
+        simulation
     }
 
     pub fn set_force(&mut self, x: f32, y: f32) { 
         self.arena.physics.set_ball_force(x, y);
     }
 
+    #[cfg(feature = "wasm")]
     pub fn iter_ball_positions(&self, iter_fn: &js_sys::Function) {
-        let arena_ball_radius = self.arena.physics.ball_radius();
-        let p 
-            = map_arena_to_screen(&self.screen.dimension, &self.arena.dimension, vector![arena_ball_radius, arena_ball_radius, arena_ball_radius]);
-        let ball_radius = p.x;
-        let ball_arena_translations = self.arena.physics.ball_translations();
-        for i in 0 .. ball_arena_translations.len() {
-            let ball_arena_translation = ball_arena_translations[i];
-            let ball_position 
-                = map_arena_to_screen(&self.screen.dimension, &self.arena.dimension, ball_arena_translation.clone());
+        for ball in self.ball_state.chunks_exact(3) {
             let this = JsValue::null();
             let _ = iter_fn.call3(&this, 
+                &JsValue::from(ball[0]), 
+                &JsValue::from(ball[1]), 
+                &JsValue::from(ball[2]));
+        }
+    }
+
+    // screen x, y, radius of each ball in turn, in the same order as ball_ids, as of the last update,
+    // interpolated between the last two steps by interpolation_alpha.
+    // This is a view onto wasm memory rather than a copy, so is only valid until the next call
+    // into the Simulation, which may move or grow that memory
+    #[cfg(feature = "wasm")]
+    pub fn ball_state(&self) -> js_sys::Float32Array {
+        unsafe { js_sys::Float32Array::view(&self.ball_state) }
+    }
+
+    // in the same order as iter_ball_positions
+    pub fn ball_ids(&self) -> Vec<u32> {
+        self.arena.physics.ball_ids()
+    }
+
+    // drops a ball of radius, in screen units, from above screen point x, y, returning its id
+    pub fn spawn_ball(&mut self, x: f32, y: f32, radius: f32) -> u32 {
+        let translation = map_screen_to_arena(&self.screen.dimension, &self.arena.dimension, 
+            Point2::new(x, y), self.arena.height_scale.drop_height());
+        let kind = self.standard_ball_of_radius(radius);
+        let id = self.arena.physics.spawn_ball(translation, kind);
+        self.refresh_ball_state();
+        id
+    }
+
+    // false if there is no such ball
+    pub fn despawn_ball(&mut self, id: u32) -> bool {
+        let despawned = self.arena.physics.despawn_ball(id);
+        self.refresh_ball_state();
+        despawned
+    }
+
+    // pours balls_per_second balls of radius, in screen units, from above screen point x, y,
+    // until removed, returning its id
+    pub fn add_emitter(&mut self, x: f32, y: f32, balls_per_second: f32, radius: f32) -> u32 {
+        let translation = map_screen_to_arena(&self.screen.dimension, &self.arena.dimension, 
+            Point2::new(x, y), 0.0);
+        let kind = self.standard_ball_of_radius(radius);
+        self.arena.add_emitter(translation.x, translation.z, balls_per_second, kind)
+    }
+
+    // false if there is no such emitter
+    pub fn remove_emitter(&mut self, id: u32) -> bool {
+        self.arena.remove_emitter(id)
+    }
+
+    // arena units per metre of elevation
+    pub fn vertical_scale(&self) -> f32 {
+        self.arena.height_scale.units_per_metre
+    }
+
+    pub fn bounds(&self) -> Option<GeoBounds> {
+        self.bounds
+    }
+
+    // calls iter_fn with longitude, latitude of each ball
+    #[cfg(feature = "wasm")]
+    pub fn iter_ball_geo_positions(&self, iter_fn: &js_sys::Function) -> Result<(), String> {
+        let bounds = self.bounds.ok_or("terrain has no geographic bounds")?;
+        for ball_arena_translation in self.arena.physics.ball_translations() {
+            let ball_position 
+                = map_arena_to_geo(&bounds, &self.arena.dimension, ball_arena_translation);
+            let this = JsValue::null();
+            let _ = iter_fn.call2(&this, 
                 &JsValue::from(ball_position.x), 
-                &JsValue::from(ball_position.y), 
-                &JsValue::from(ball_radius));
+                &JsValue::from(ball_position.y));
         }
+        Ok(())
+    }
+
+    // the catchment containing the arena point x, z, numbered from 1
+    pub fn catchment_at(&self, x: f32, z: f32) -> u32 {
+        let (rows, columns) = self.catchments.shape();
+        let cell = map_arena_to_cell(&self.arena.dimension, rows, columns, vector![x, 0.0, z]);
+        self.catchments[cell] as u32
     }
 
-    pub fn update(&mut self, _elapsed_since_last_update: u32) {
-        self.arena.physics.step();
+    // the catchment each ball is in, in the same order as ball_state, as of the last step.
+    // Like ball_state, a view onto wasm memory that is only valid until the next call
+    // into the Simulation
+    #[cfg(feature = "wasm")]
+    pub fn ball_catchments(&self) -> js_sys::Uint32Array {
+        unsafe { js_sys::Uint32Array::view(&self.ball_catchments) }
+    }
+
+    // steps balls and water for as long as has elapsed, in milliseconds, in steps of a fixed size, 
+    // carrying over any time left to the next update
+    pub fn update(&mut self, elapsed_since_last_update: f32) {
+        let steps = self.timestep.advance(elapsed_since_last_update / 1000.0);
+        if steps > 0 {
+            for _ in 1..steps {
+                self.step();
+            }
+            let mut previous = std::mem::take(&mut self.previous_ball_state);
+            self.write_ball_state(&mut previous);
+            self.previous_ball_state = previous;
+            let balls_before = self.arena.physics.num_balls();
+            self.step();
+            let mut current = std::mem::take(&mut self.current_ball_state);
+            self.write_ball_state(&mut current);
+            self.current_ball_state = current;
+            self.refresh_ball_catchments();
+            if self.arena.physics.num_balls() != balls_before {
+                // balls came or went in the last step, so there is nothing to interpolate from
+                self.previous_ball_state.clone_from(&self.current_ball_state);
+            }
+            self.refresh_water_state();
+        }
+        self.interpolate_ball_state();
+    }
+
+    // e.g. Tuning::many_bodies(), for thousands of balls
+    pub fn set_tuning(&mut self, tuning: &Tuning) {
+        self.arena.physics.tune(*tuning);
+    }
+
+    // records contacts between balls and anything else, with at least force_threshold force
+    // between them to record the force, until disabled
+    pub fn enable_contact_events(&mut self, force_threshold: f32) {
+        self.arena.physics.record_contacts(Some(force_threshold));
+    }
+
+    pub fn disable_contact_events(&mut self) {
+        self.arena.physics.record_contacts(None);
+    }
+
+    // contact events of the steps taken since the last call, step by step, see ContactCollector::drain
+    pub fn drain_contact_events(&mut self) -> Vec<ContactEvent> {
+        self.arena.physics.drain_contacts()
+    }
+
+    // how far between the last two steps ball_state is, from 0 to 1
+    pub fn interpolation_alpha(&self) -> f32 {
+        self.timestep.alpha()
+    }
+
+    // steps to take at most in one update, beyond which the simulation falls behind wall-clock time
+    pub fn set_max_steps_per_update(&mut self, max_steps: usize) {
+        self.timestep.set_max_steps(max_steps);
     }   
+
+    pub fn add_water_source(&mut self, x: f32, y: f32, rate: f32) {
+        let (row, column) = self.water_cell(x, y);
+        self.water.add_source(row, column, rate);
+    }
+
+    pub fn add_water_sink(&mut self, x: f32, y: f32, rate: f32) {
+        let (row, column) = self.water_cell(x, y);
+        self.water.add_sink(row, column, rate);
+    }
+
+    // the balls, the water and the time carried over to the next update
+    pub fn snapshot(&self) -> Vec<u8> {
+        bincode::serialize(&(&self.arena, &self.water, &self.timestep)).unwrap()
+    }
+
+    // emitters carry on, with the same ids, as they aren't part of a snapshot
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), String> {
+        let (mut arena, water, timestep): (Arena, ShallowWater, FixedTimestep) = bincode::deserialize(snapshot)
+            .map_err(|e| format!("could not restore snapshot: {}", e))?;
+        arena.take_emitters_from(&mut self.arena);
+        self.arena = arena;
+        self.water = water;
+        self.timestep = timestep;
+        self.refresh_ball_state();
+        self.refresh_water_state();
+        Ok(())
+    }
+
+    pub fn as_grayscale_water_depth_image(&self) -> Vec<u8> {
+        as_grayscale_image(self.water.depths())
+    }
+
+    pub fn water_rows(&self) -> usize {
+        self.water.rows()
+    }
+
+    pub fn water_columns(&self) -> usize {
+        self.water.columns()
+    }
+
+    // depth of water in each cell, in metres, row by row, as of the last step.
+    // Like ball_state, a view onto wasm memory that is only valid until the next call
+    // into the Simulation
+    #[cfg(feature = "wasm")]
+    pub fn water_state(&self) -> js_sys::Float32Array {
+        unsafe { js_sys::Float32Array::view(&self.water_state) }
+    }
+}
+
+impl Simulation {
+    // as ball_state, for use outside the browser
+    pub fn ball_state_values(&self) -> &[f32] {
+        &self.ball_state
+    }
+
+    pub fn water_depths(&self) -> &DMatrix<Real> {
+        self.water.depths()
+    }
+
+    // as ball_catchments, for use outside the browser
+    pub fn ball_catchment_values(&self) -> &[u32] {
+        &self.ball_catchments
+    }
+
+    // as water_state, for use outside the browser
+    pub fn water_state_values(&self) -> &[f32] {
+        &self.water_state
+    }
+
+    fn step(&mut self) {
+        self.arena.step();
+        self.water.step(self.arena.physics.dt());
+    }
+
+    // after balls have been changed other than by stepping, so there is nothing to interpolate
+    fn refresh_ball_state(&mut self) {
+        let mut current = std::mem::take(&mut self.current_ball_state);
+        self.write_ball_state(&mut current);
+        self.current_ball_state = current;
+        self.previous_ball_state.clone_from(&self.current_ball_state);
+        self.interpolate_ball_state();
+        self.refresh_ball_catchments();
+    }
+
+    // reuses the buffer, as for ball_state
+    fn refresh_ball_catchments(&mut self) {
+        let mut catchments = std::mem::take(&mut self.ball_catchments);
+        catchments.clear();
+        catchments.extend(self.arena.physics.iter_balls()
+            .map(|(translation, _radius)| self.catchment_at(translation.x, translation.z)));
+        self.ball_catchments = catchments;
+    }
+
+    // reuses the buffer, so that there's no allocation unless the number of balls grows
+    fn write_ball_state(&self, ball_state: &mut Vec<f32>) {
+        let (screen, arena) = (&self.screen.dimension, &self.arena.dimension);
+        ball_state.clear();
+        for (translation, radius) in self.arena.physics.iter_balls() {
+            let position = map_arena_to_screen(screen, arena, *translation);
+            let screen_radius = radius * screen.width / arena.width;
+            ball_state.extend_from_slice(&[position.x, position.y, screen_radius]);
+        }
+    }
+
+    // reuses the buffer, as it's the same size every time
+    fn refresh_water_state(&mut self) {
+        self.water_state.clear();
+        for row in self.water.depths().row_iter() {
+            self.water_state.extend(row.iter());
+        }
+    }
+
+    fn interpolate_ball_state(&mut self) {
+        let alpha = self.timestep.alpha();
+        self.ball_state.clear();
+        self.ball_state.extend(self.previous_ball_state.iter().zip(&self.current_ball_state)
+            .map(|(previous, current)| previous + (current - previous) * alpha));
+    }
+
+    fn standard_ball_of_radius(&self, radius: f32) -> BallKind {
+        let arena_radius = radius * self.arena.dimension.width / self.screen.dimension.width;
+        BallKind { radius: arena_radius / self.arena.physics.ball_radius(), ..BallKind::ball() }
+    }
+
+    fn water_cell(&self, x: f32, y: f32) -> (usize, usize) {
+        let screen = &self.screen.dimension;
+        let column = ((x / screen.width) * (self.water.columns() as f32)) as usize;
+        let row = ((y / screen.depth) * (self.water.rows() as f32)) as usize;
+        (row.min(self.water.rows() - 1), column.min(self.water.columns() - 1))
+    }
+}
+
+#[cfg(test)]
+mod simulation_tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    fn simulation(num_balls: u32) -> Simulation {
+        let terrain = Terrain::from_elevations(DMatrix::from_fn(20, 20, |row, column| ((row + column) % 3) as Real));
+        Simulation::new(num_balls, &terrain, &Screen::new(200.0, 200.0), 42, None, &Placement::uniform(), &BallMix::default())
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_update_interpolates_ball_state() {
+        let mut simulation = simulation(10);
+        let dt_millis = simulation.arena.physics.dt() * 1000.0;
+        // pushed sideways, as falling doesn't move balls on screen
+        simulation.set_force(50.0, 0.0);
+        simulation.update(dt_millis * 2.0);
+        // a step and a half, so half way from the state after the first to that after the second
+        simulation.update(dt_millis * 1.5);
+        let alpha = simulation.interpolation_alpha();
+        assert!((alpha - 0.5).abs() < 0.001, "{}", alpha);
+
+        let (previous, current) = (&simulation.previous_ball_state, &simulation.current_ball_state);
+        assert_eq!(30, simulation.ball_state_values().len());
+        assert_ne!(previous, current);
+        for ((value, previous), current) in simulation.ball_state_values().iter().zip(previous).zip(current) {
+            let expected = previous + (current - previous) * alpha;
+            assert!((value - expected).abs() < 0.0001, "{} {}", value, expected);
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_update_steps_water() {
+        let mut simulation = simulation(0);
+        let dt = simulation.arena.physics.dt();
+        simulation.add_water_source(100.0, 100.0, 1.0);
+        // two steps' worth, with the rest carried over
+        simulation.update(dt * 1000.0 * 2.5);
+        let volume = simulation.water.total_volume();
+        assert!((volume - 2.0 * dt).abs() < 0.0001, "{}", volume);
+        let depths = simulation.water.depths();
+        // row by row, so in the order of the transpose's columns
+        let row_by_row: Vec<f32> = depths.transpose().iter().copied().collect();
+        assert_eq!(simulation.water_state_values(), row_by_row.as_slice());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_update_refreshes_ball_catchments() {
+        let mut simulation = simulation(10);
+        simulation.set_force(50.0, 0.0);
+        let dt = simulation.arena.physics.dt();
+        for _ in 0..10 {
+            simulation.update(dt * 1000.0 * 3.0);
+            let expected: Vec<u32> = simulation.arena.physics.iter_balls()
+                .map(|(translation, _radius)| simulation.catchment_at(translation.x, translation.z))
+                .collect();
+            assert_eq!(expected.as_slice(), simulation.ball_catchment_values());
+        }
+        simulation.spawn_ball(100.0, 100.0, 2.0);
+        assert_eq!(11, simulation.ball_catchment_values().len());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_snapshot_restores_water_and_timestep() {
+        let mut original = simulation(10);
+        let dt = original.arena.physics.dt();
+        original.add_water_source(100.0, 100.0, 1.0);
+        // leaves half a step carried over
+        original.update(dt * 1000.0 * 2.5);
+        let snapshot = original.snapshot();
+
+        // one with no time carried over, and one with more than the original
+        let mut restored = [simulation(10), simulation(10)];
+        restored[1].update(dt * 1000.0 * 0.6);
+        for simulation in restored.iter_mut() {
+            simulation.restore(&snapshot).unwrap();
+            assert_eq!(original.water_state_values(), simulation.water_state_values());
+        }
+        // three steps for the original and each copy, had their carried-over times not been restored,
+        // two and three
+        original.update(dt * 1000.0 * 2.6);
+        for simulation in restored.iter_mut() {
+            simulation.update(dt * 1000.0 * 2.6);
+            assert_eq!(original.water_state_values(), simulation.water_state_values());
+            assert_eq!(original.ball_state_values(), simulation.ball_state_values());
+        }
+        assert!(original.water.total_volume() > 4.0 * dt);
+    }
 }
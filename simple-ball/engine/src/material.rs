@@ -0,0 +1,137 @@
+// What balls are made of, and how big they are
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rapier3d::prelude::*;
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BallKind {
+    // in multiples of the standard ball radius, which depends on the arena size
+    pub radius: Real,
+    pub density: Real,
+    pub friction: Real,
+    pub restitution: Real
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl BallKind {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(radius: Real, density: Real, friction: Real, restitution: Real) -> BallKind {
+        BallKind { radius, density, friction, restitution }
+    }
+
+    // as balls have always been, i.e. rapier's defaults, but bouncy
+    pub fn ball() -> BallKind {
+        BallKind::new(1.0, 1.0, 0.5, 0.8)
+    }
+
+    pub fn sand() -> BallKind {
+        BallKind::new(0.5, 1.6, 0.9, 0.1)
+    }
+
+    pub fn pebble() -> BallKind {
+        BallKind::new(1.0, 2.6, 0.6, 0.4)
+    }
+
+    pub fn boulder() -> BallKind {
+        BallKind::new(3.0, 2.7, 0.8, 0.2)
+    }
+}
+
+// kinds of ball, each with a weight for how likely a ball is to be of that kind
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BallMix {
+    kinds: Vec<(BallKind, f64)>
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl BallMix {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> BallMix {
+        BallMix { kinds: Vec::new() }
+    }
+
+    pub fn only(kind: BallKind) -> BallMix {
+        BallMix { kinds: vec![(kind, 1.0)] }
+    }
+
+    // weight must be more than 0, so that every kind in the mix can be chosen
+    pub fn with(&self, kind: BallKind, weight: f64) -> Result<BallMix, String> {
+        let total: f64 = self.kinds.iter().map(|(_, weight)| weight).sum::<f64>() + weight;
+        if weight.is_nan() || weight <= 0.0 || !total.is_finite() {
+            return Err(format!("expected a weight more than 0, and a finite total weight, but got {} for {:?}", weight, kind));
+        }
+        let mut kinds = self.kinds.clone();
+        kinds.push((kind, weight));
+        Ok(BallMix { kinds })
+    }
+
+    // the largest radius of any kind, in standard ball radii
+    pub fn max_radius(&self) -> Real {
+        self.kinds.iter().map(|(kind, _)| kind.radius).fold(1.0, Real::max)
+    }
+}
+
+impl Default for BallMix {
+    fn default() -> BallMix {
+        BallMix::only(BallKind::ball())
+    }
+}
+
+impl BallMix {
+    // a kind for each of num_balls, chosen by weight, and the same for the same seed,
+    // or standard balls if the mix is empty
+    pub fn choose(&self, num_balls: usize, seed: u64) -> Vec<BallKind> {
+        if self.kinds.is_empty() {
+            return vec![BallKind::ball(); num_balls];
+        }
+        let weights: Vec<f64> = self.kinds.iter().map(|(_, weight)| *weight).collect();
+        let distribution = WeightedIndex::new(&weights).expect("weights are checked by with");
+        // a different stream to that used for placement, so each can change independently
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(1);
+        (0..num_balls).map(|_| self.kinds[distribution.sample(&mut rng)].0).collect()
+    }
+}
+
+#[cfg(test)]
+mod material_tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_choose() {
+        let mix = BallMix::new()
+            .with(BallKind::sand(), 3.0).unwrap()
+            .with(BallKind::boulder(), 1.0).unwrap();
+        let kinds = mix.choose(400, 42);
+
+        assert_eq!(kinds, mix.choose(400, 42));
+        assert_ne!(kinds, mix.choose(400, 43));
+        let boulders = kinds.iter().filter(|kind| **kind == BallKind::boulder()).count();
+        assert!(boulders > 70 && boulders < 130, "{}", boulders);
+        assert_eq!(400 - boulders, kinds.iter().filter(|kind| **kind == BallKind::sand()).count());
+        assert_eq!(3.0, mix.max_radius());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_empty_mix_is_standard_balls() {
+        assert_eq!(vec![BallKind::ball(); 3], BallMix::new().choose(3, 42));
+        assert_eq!(vec![BallKind::ball(); 3], BallMix::default().choose(3, 42));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_bad_weights() {
+        for weight in [-1.0, 0.0, f64::NAN, f64::INFINITY] {
+            assert!(BallMix::new().with(BallKind::sand(), weight).is_err(), "{}", weight);
+        }
+        let huge = BallMix::new().with(BallKind::sand(), f64::MAX).unwrap();
+        assert!(huge.with(BallKind::boulder(), f64::MAX).is_err());
+    }
+}
@@ -0,0 +1,396 @@
+// Where balls start off in the arena, before they are dropped onto the terrain
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rapier3d::prelude::*;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::dimension::*;
+use crate::resample::*;
+use crate::terrain::*;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Strategy {
+    Uniform,
+    LowBiased,
+    HighBiased,
+    // x = column, y = row of the terrain, as for the elevations
+    Points(Vec<(Real, Real)>),
+    // in ball diameters
+    PoissonDisk { spacing: Real },
+    Contour { elevation: Real },
+    // ignoring depressions shallower than min_depth, in metres
+    Basins { min_depth: Real }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    strategy: Strategy
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Placement {
+    // equally likely anywhere
+    pub fn uniform() -> Placement {
+        Placement { strategy: Strategy::Uniform }
+    }
+
+    // much more likely the lower the terrain, so balls start near where they settle
+    pub fn low_biased() -> Placement {
+        Placement { strategy: Strategy::LowBiased }
+    }
+
+    // much more likely the higher the terrain, like rain falling on peaks
+    pub fn high_biased() -> Placement {
+        Placement { strategy: Strategy::HighBiased }
+    }
+
+    // one ball at each xs[i], ys[i] in turn, up to the number of balls asked for,
+    // where x, y is in cells of the terrain, with y going from top->bottom
+    pub fn points(xs: Vec<Real>, ys: Vec<Real>) -> Result<Placement, String> {
+        if xs.len() != ys.len() {
+            return Err(format!("expected as many ys as xs but got {} xs and {} ys", xs.len(), ys.len()));
+        }
+        Ok(Placement { strategy: Strategy::Points(xs.into_iter().zip(ys).collect()) })
+    }
+
+    // anywhere, but with no two balls closer than spacing ball diameters
+    pub fn poisson_disk(spacing: Real) -> Result<Placement, String> {
+        if spacing.is_nan() || spacing <= 0.0 {
+            return Err(format!("expected a spacing greater than 0 but got {}", spacing));
+        }
+        Ok(Placement { strategy: Strategy::PoissonDisk { spacing } })
+    }
+
+    // on the cells of the terrain which the contour at elevation passes through
+    pub fn contour(elevation: Real) -> Placement {
+        Placement { strategy: Strategy::Contour { elevation } }
+    }
+
+    // in the depressions of the terrain, more likely the deeper below the level at which
+    // they would spill over, ignoring those shallower than min_depth as artefacts of the data
+    pub fn basins(min_depth: Real) -> Placement {
+        Placement { strategy: Strategy::Basins { min_depth } }
+    }
+}
+
+impl Placement {
+    // may place fewer than num_balls, if there is not enough room, except when placing on a grid,
+    // where any balls beyond the number of cells are placed in layers above
+    pub fn place(&self, num_balls: usize, ball_radius: Real, dimension: &Dimension, terrain: &Terrain, y: Real, seed: u64) -> Vec<Vector<Real>> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        match &self.strategy {
+            Strategy::Points(points) => {
+                let x_scale = dimension.width / (terrain.width as Real);
+                let z_scale = dimension.depth / (terrain.height as Real);
+                // at the centre of the cell, kept clear of the edges
+                points.iter().take(num_balls).map(|(x, row)| vector![
+                    within((x + 0.5) * x_scale, ball_radius, dimension.width),
+                    y,
+                    within(dimension.depth - (row + 0.5) * z_scale, ball_radius, dimension.depth)
+                ]).collect()
+            },
+            Strategy::PoissonDisk { spacing } => {
+                let min_distance = spacing * ball_radius * 2.0;
+                poisson_disk(dimension, ball_radius, min_distance, num_balls, &mut rng).into_iter().map(|(x, z)| vector![x, y, z]).collect()
+            },
+            _ => self.place_on_grid(num_balls, ball_radius, dimension, terrain, y, &mut rng)
+        }
+    }
+
+    // chooses cells from a grid of cells one ball across, weighted by the strategy
+    fn place_on_grid(&self, num_balls: usize, ball_radius: Real, dimension: &Dimension, terrain: &Terrain, y: Real, rng: &mut ChaCha8Rng) -> Vec<Vector<Real>> {
+        let containing_box_side_length = ball_radius * 2.0;
+        let possible_grid_positions_x = (dimension.width / containing_box_side_length).floor() as usize;
+        let possible_grid_positions_z = (dimension.depth / containing_box_side_length).floor() as usize;
+        log::debug!("possible_grid_positions: {}x{}", possible_grid_positions_x, possible_grid_positions_z);
+        let sized_terrain = terrain.resample(possible_grid_positions_x, possible_grid_positions_z, ResampleMethod::Bilinear);
+        log::debug!("Sized terrain: {}x{}", sized_terrain.width, sized_terrain.height);
+        let max_bucket_value = 20.0;
+        let heightfield = sized_terrain.as_xz_heightfield(max_bucket_value);
+        log::debug!("Converted to heightfield, shape: {:?}", heightfield.shape());
+        let elevations = sized_terrain.elevations();
+        // from the terrain as given, so that resampling doesn't smooth depressions away
+        let sink_depths = match self.strategy {
+            Strategy::Basins { min_depth } => resample(&terrain.fill_shallow_depressions(min_depth).sink_depths(),
+                sized_terrain.height, sized_terrain.width, ResampleMethod::Bilinear),
+            _ => DMatrix::zeros(0, 0)
+        };
+        let possible_grid_positions : Vec<(u32, u32)>
+            = (0..sized_terrain.height).flat_map(|z| {
+                let row : Vec<(u32, u32)>
+                    = (0..sized_terrain.width).map(|x| {
+                        (x as u32, z as u32)
+                    }).collect();
+                row
+            }).collect();
+        log::debug!("Created possible grid positions");
+        let probabilities : Vec<((u32, u32), f64)>
+            = possible_grid_positions.iter().map(|(x, z)| {
+                let row = *z as usize;
+                let column = *x as usize;
+                let index = (row, column);
+                let bucketed_height = *heightfield.index(index) as f64;
+                let probability = match self.strategy {
+                    Strategy::LowBiased => 2.0f64.powf((max_bucket_value as f64) - bucketed_height),
+                    Strategy::HighBiased => 2.0f64.powf(bucketed_height),
+                    Strategy::Contour { elevation } => {
+                        // the heightfield is flipped relative to the elevations
+                        let flipped_row = sized_terrain.height - 1 - row;
+                        if crosses(elevations, flipped_row, column, elevation) { 1.0 } else { 0.0 }
+                    },
+                    Strategy::Basins { .. } => sink_depths[(sized_terrain.height - 1 - row, column)] as f64,
+                    _ => 1.0
+                };
+                log::trace!("{:?} -> {:?}", bucketed_height, probability);
+                ((*x, *z), probability)
+            }).collect();
+        let x_scale_up = dimension.width / (sized_terrain.width as f32);
+        let z_scale_up = dimension.depth / (sized_terrain.height as f32);
+        // with more balls than cells, the rest go in layers above, one ball apart
+        let mut balls = Vec::new();
+        let mut layer_y = y;
+        while balls.len() < num_balls {
+            let selected
+                = probabilities.choose_multiple_weighted(
+                    rng,
+                    num_balls - balls.len(),
+                    |(_point, probability)| *probability).unwrap();
+            let layer: Vec<Vector<Real>> = selected
+                .filter(|(_point, probability)| *probability > 0.0)
+                .map(|((x, z), _probability)| {
+                    // at the centre of the cell, which is at least a ball across, but for rounding
+                    vector![
+                        within((*x as f32 + 0.5) * x_scale_up, ball_radius, dimension.width),
+                        layer_y,
+                        within((*z as f32 + 0.5) * z_scale_up, ball_radius, dimension.depth)]
+                }).collect();
+            if layer.is_empty() {
+                break;
+            }
+            balls.extend(layer);
+            layer_y += containing_box_side_length;
+        }
+        balls
+    }
+}
+
+// value, moved if need be so that a ball of radius there is within 0..extent
+fn within(value: Real, radius: Real, extent: Real) -> Real {
+    value.clamp(radius, (extent - radius).max(radius))
+}
+
+// whether the contour at elevation passes through a cell, i.e. whether it is
+// between the lowest and highest of the cell and its neighbours
+fn crosses(elevations: &DMatrix<Real>, row: usize, column: usize, elevation: Real) -> bool {
+    let rows = row.saturating_sub(1)..=(row + 1).min(elevations.nrows() - 1);
+    let columns = column.saturating_sub(1)..=(column + 1).min(elevations.ncols() - 1);
+    let neighbourhood = elevations.view(
+        (*rows.start(), *columns.start()),
+        (rows.end() - rows.start() + 1, columns.end() - columns.start() + 1));
+    neighbourhood.min() <= elevation && elevation <= neighbourhood.max()
+}
+
+// up to max_points x, z points no closer than min_distance to each other, and at least
+// ball_radius from the edges, as in Bridson (2007)
+fn poisson_disk(dimension: &Dimension, ball_radius: Real, min_distance: Real, max_points: usize, rng: &mut ChaCha8Rng) -> Vec<(Real, Real)> {
+    let attempts = 30;
+    let (min_x, max_x) = (ball_radius, dimension.width - ball_radius);
+    let (min_z, max_z) = (ball_radius, dimension.depth - ball_radius);
+    if min_x >= max_x || min_z >= max_z || max_points == 0 {
+        return vec![];
+    }
+    // each grid cell can hold at most one point
+    let cell_size = min_distance / 2.0f32.sqrt();
+    let columns = ((max_x - min_x) / cell_size).ceil() as usize + 1;
+    let rows = ((max_z - min_z) / cell_size).ceil() as usize + 1;
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+    let cell_of = |(x, z): (Real, Real)| (((z - min_z) / cell_size) as usize, ((x - min_x) / cell_size) as usize);
+
+    let mut points = vec![];
+    let mut active = vec![];
+    let first = (rng.gen_range(min_x..max_x), rng.gen_range(min_z..max_z));
+    let (row, column) = cell_of(first);
+    grid[row * columns + column] = Some(0);
+    points.push(first);
+    active.push(0);
+    while !active.is_empty() && points.len() < max_points {
+        let active_index = rng.gen_range(0..active.len());
+        let (x, z) = points[active[active_index]];
+        let mut found = false;
+        for _ in 0..attempts {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let distance = rng.gen_range(min_distance..2.0 * min_distance);
+            let candidate = (x + distance * angle.cos(), z + distance * angle.sin());
+            if candidate.0 < min_x || candidate.0 > max_x || candidate.1 < min_z || candidate.1 > max_z {
+                continue;
+            }
+            let (row, column) = cell_of(candidate);
+            let too_close = (row.saturating_sub(2)..(row + 3).min(rows)).any(|r| {
+                (column.saturating_sub(2)..(column + 3).min(columns)).any(|c| {
+                    grid[r * columns + c].is_some_and(|other| {
+                        let (other_x, other_z) = points[other];
+                        (candidate.0 - other_x).hypot(candidate.1 - other_z) < min_distance
+                    })
+                })
+            });
+            if !too_close {
+                grid[row * columns + column] = Some(points.len());
+                active.push(points.len());
+                points.push(candidate);
+                found = true;
+                break;
+            }
+        }
+        if !found {
+            active.swap_remove(active_index);
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod placement_tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    // rises from 0 on the left to 19 on the right
+    fn ramp_terrain() -> Terrain {
+        Terrain::from_elevations(DMatrix::from_fn(20, 20, |_row, column| column as Real))
+    }
+
+    fn place(placement: &Placement, num_balls: usize, seed: u64) -> Vec<Vector<Real>> {
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        placement.place(num_balls, 0.1, &dimension, &ramp_terrain(), 100.0, seed)
+    }
+
+    fn mean_x(balls: &[Vector<Real>]) -> Real {
+        balls.iter().map(|ball| ball.x).sum::<Real>() / (balls.len() as Real)
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_deterministic() {
+        let placements = [
+            Placement::uniform(),
+            Placement::low_biased(),
+            Placement::high_biased(),
+            Placement::poisson_disk(2.0).unwrap(),
+            Placement::contour(10.0)
+        ];
+        for placement in placements {
+            assert_eq!(place(&placement, 20, 42), place(&placement, 20, 42), "{:?}", placement);
+            assert_ne!(place(&placement, 20, 1), place(&placement, 20, 2), "{:?}", placement);
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_biased() {
+        let uniform = mean_x(&place(&Placement::uniform(), 50, 42));
+        let low = mean_x(&place(&Placement::low_biased(), 50, 42));
+        let high = mean_x(&place(&Placement::high_biased(), 50, 42));
+        assert!(low < uniform && uniform < high, "{} {} {}", low, uniform, high);
+        assert!(low < 2.0, "{}", low);
+        assert!(high > 8.0, "{}", high);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_layers() {
+        // 20x20 cells of half an arena unit, so two full layers and part of a third
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let balls = Placement::uniform().place(1000, 0.25, &dimension, &ramp_terrain(), 100.0, 42);
+        assert_eq!(1000, balls.len());
+        let layer = |y: Real| balls.iter().filter(|ball| (ball.y - y).abs() < 0.001).count();
+        assert_eq!(400, layer(100.0));
+        assert_eq!(400, layer(100.5));
+        assert_eq!(200, layer(101.0));
+        // nothing shares a cell within a layer
+        for (i, a) in balls.iter().enumerate() {
+            assert!(balls[i + 1..].iter().all(|b| (a - b).norm() > 0.25));
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_grid_is_one_ball_across() {
+        // the 20x20 terrain is resampled to 50x50 cells, one ball across, rather than being left as is
+        let balls = place(&Placement::uniform(), 2500, 42);
+        assert_eq!(2500, balls.len());
+        assert!(balls.iter().all(|ball| ball.y == 100.0));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_points() {
+        // the top-left of the terrain is at the back of the arena
+        let placement = Placement::points(vec![0.0, 10.0, 19.9], vec![0.0, 5.0, 19.0]).unwrap();
+        let expected = vec![
+            vector![0.25, 100.0, 9.75],
+            vector![5.25, 100.0, 7.25],
+            // half a cell in from the edge is beyond it, so is moved back by a ball radius
+            vector![9.9, 100.0, 0.25]
+        ];
+        assert_eq!(expected, place(&placement, 50, 42));
+        // only as many as asked for
+        assert_eq!(expected[..2], place(&placement, 2, 42));
+
+        assert!(Placement::points(vec![0.0, 1.0], vec![0.0]).is_err());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_poisson_disk() {
+        let balls = place(&Placement::poisson_disk(3.0).unwrap(), 100, 42);
+        assert_eq!(100, balls.len());
+        for (i, a) in balls.iter().enumerate() {
+            for b in &balls[i + 1..] {
+                assert!((a - b).norm() >= 3.0 * 0.2, "{} and {} too close", a, b);
+            }
+            assert!(a.x >= 0.1 && a.x <= 9.9 && a.z >= 0.1 && a.z <= 9.9);
+        }
+        // with too much spacing, there is only room for a few
+        assert!(place(&Placement::poisson_disk(20.0).unwrap(), 100, 42).len() < 10);
+        assert_eq!(5, place(&Placement::poisson_disk(3.0).unwrap(), 5, 42).len());
+        assert!(place(&Placement::poisson_disk(3.0).unwrap(), 0, 42).is_empty());
+
+        assert!(Placement::poisson_disk(0.0).is_err());
+        assert!(Placement::poisson_disk(-1.0).is_err());
+        assert!(Placement::poisson_disk(Real::NAN).is_err());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_basins() {
+        // a pit 5 deep on the right, and one only 0.5 deep on the left, in otherwise flat terrain
+        let terrain = Terrain::from_elevations(DMatrix::from_fn(20, 20, |row, column| {
+            match (row, column) {
+                (8..=11, 14..=17) => -5.0,
+                (8..=11, 2..=5) => -0.5,
+                _ => 0.0
+            }
+        }));
+        let dimension = Dimension { width: 10.0, depth: 10.0 };
+        let place = |min_depth: Real| Placement::basins(min_depth).place(20, 0.25, &dimension, &terrain, 100.0, 42);
+
+        let deep_only = place(1.0);
+        assert_eq!(20, deep_only.len());
+        assert!(deep_only.iter().all(|ball| ball.x > 6.5 && ball.x < 9.5), "{:?}", deep_only);
+
+        let both = place(0.0);
+        assert_eq!(20, both.len());
+        assert!(both.iter().all(|ball| ball.x < 3.5 || ball.x > 6.5), "{:?}", both);
+        // but far more in the deeper
+        assert!(both.iter().filter(|ball| ball.x > 6.5).count() > 10);
+
+        // with no depressions deep enough, there is nowhere to place them
+        assert!(place(10.0).is_empty());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_contour() {
+        // elevation 9.5 is between columns 9 and 10, each half an arena unit across
+        let balls = place(&Placement::contour(9.5), 10, 42);
+        assert_eq!(10, balls.len());
+        for ball in balls {
+            assert!(ball.x > 4.0 && ball.x < 6.0, "{}", ball);
+        }
+    }
+}
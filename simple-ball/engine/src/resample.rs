@@ -0,0 +1,203 @@
+// Resampling of elevation grids to arbitrary sizes, up or down.
+// Cells are treated as samples at their centres, so the corners of the
+// grid stay in place whatever the new size
+use rapier3d::prelude::*;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleMethod {
+    Nearest,
+    Bilinear,
+    // Catmull-Rom
+    Bicubic
+}
+
+// Shrinking with Bilinear or Bicubic first averages the cells each new cell covers,
+// as sampling only a few of them would alias, e.g. turning ridges into dotted lines
+pub fn resample(values: &DMatrix<Real>, rows: usize, columns: usize, method: ResampleMethod) -> DMatrix<Real> {
+    if values.is_empty() {
+        // nothing to sample, so as if flat
+        return DMatrix::zeros(rows, columns);
+    }
+    if method != ResampleMethod::Nearest && (rows < values.nrows() || columns < values.ncols()) {
+        let averaged = area_average(values, rows.min(values.nrows()), columns.min(values.ncols()));
+        return interpolate(&averaged, rows, columns, method);
+    }
+    interpolate(values, rows, columns, method)
+}
+
+fn interpolate(values: &DMatrix<Real>, rows: usize, columns: usize, method: ResampleMethod) -> DMatrix<Real> {
+    let row_scale = values.nrows() as Real / rows as Real;
+    let column_scale = values.ncols() as Real / columns as Real;
+    DMatrix::from_fn(rows, columns, |row, column| {
+        // centre of the new cell, in terms of the cells of values
+        let source_row = (row as Real + 0.5) * row_scale - 0.5;
+        let source_column = (column as Real + 0.5) * column_scale - 0.5;
+        match method {
+            ResampleMethod::Nearest => nearest(values, source_row, source_column),
+            ResampleMethod::Bilinear => bilinear(values, source_row, source_column),
+            ResampleMethod::Bicubic => bicubic(values, source_row, source_column)
+        }
+    })
+}
+
+// each new cell is the mean of the cells it covers, weighted by how much of each it covers
+fn area_average(values: &DMatrix<Real>, rows: usize, columns: usize) -> DMatrix<Real> {
+    let row_weights = coverage(values.nrows(), rows);
+    let column_weights = coverage(values.ncols(), columns);
+    DMatrix::from_fn(rows, columns, |row, column| {
+        let mut sum = 0.0;
+        for (source_row, row_weight) in &row_weights[row] {
+            for (source_column, column_weight) in &column_weights[column] {
+                sum += row_weight * column_weight * values[(*source_row, *source_column)];
+            }
+        }
+        sum
+    })
+}
+
+// for each of to cells, the from cells it covers and the proportion of it each makes up,
+// where to is no more than from
+fn coverage(from: usize, to: usize) -> Vec<Vec<(usize, Real)>> {
+    let scale = from as f64 / to as f64;
+    (0..to).map(|cell| {
+        let (start, end) = (cell as f64 * scale, (cell + 1) as f64 * scale);
+        let last = (end.ceil() as usize).min(from);
+        (start.floor() as usize..last).map(|source| {
+            let covered = end.min((source + 1) as f64) - start.max(source as f64);
+            (source, (covered / scale) as Real)
+        }).collect()
+    }).collect()
+}
+
+// values beyond the edges repeat the edge, of which there must be at least one
+fn clamped(values: &DMatrix<Real>, row: isize, column: isize) -> Real {
+    let row = row.clamp(0, values.nrows() as isize - 1) as usize;
+    let column = column.clamp(0, values.ncols() as isize - 1) as usize;
+    values[(row, column)]
+}
+
+fn nearest(values: &DMatrix<Real>, row: Real, column: Real) -> Real {
+    clamped(values, row.round() as isize, column.round() as isize)
+}
+
+fn bilinear(values: &DMatrix<Real>, row: Real, column: Real) -> Real {
+    let (row_floor, column_floor) = (row.floor(), column.floor());
+    let (dr, dc) = (row - row_floor, column - column_floor);
+    let (r, c) = (row_floor as isize, column_floor as isize);
+    let top = clamped(values, r, c) * (1.0 - dc) + clamped(values, r, c + 1) * dc;
+    let bottom = clamped(values, r + 1, c) * (1.0 - dc) + clamped(values, r + 1, c + 1) * dc;
+    top * (1.0 - dr) + bottom * dr
+}
+
+// weights for the samples at -1, 0, 1, 2 relative to the floor of the position
+fn catmull_rom_weights(t: Real) -> [Real; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2)
+    ]
+}
+
+fn bicubic(values: &DMatrix<Real>, row: Real, column: Real) -> Real {
+    let (row_floor, column_floor) = (row.floor(), column.floor());
+    let row_weights = catmull_rom_weights(row - row_floor);
+    let column_weights = catmull_rom_weights(column - column_floor);
+    let (r, c) = (row_floor as isize, column_floor as isize);
+    let mut sum = 0.0;
+    for (i, row_weight) in row_weights.iter().enumerate() {
+        for (j, column_weight) in column_weights.iter().enumerate() {
+            let value = clamped(values, r + i as isize - 1, c + j as isize - 1);
+            sum += row_weight * column_weight * value;
+        }
+    }
+    sum
+}
+
+#[cfg(test)]
+mod resample_tests {
+    use nalgebra::dmatrix;
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    const METHODS: [ResampleMethod; 3] = [ResampleMethod::Nearest, ResampleMethod::Bilinear, ResampleMethod::Bicubic];
+
+    fn assert_near(expected: &DMatrix<Real>, actual: &DMatrix<Real>) {
+        assert_eq!(expected.shape(), actual.shape());
+        for (e, a) in expected.iter().zip(actual.iter()) {
+            assert!((e - a).abs() < 0.0001, "expected {} but was {}", expected, actual);
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_same_size_is_unchanged() {
+        let values = dmatrix![1.0, 2.0, 3.0; 4.0, 5.0, 6.0];
+        for method in METHODS {
+            assert_near(&values, &resample(&values, 2, 3, method));
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_flat_stays_flat() {
+        let values = DMatrix::from_element(5, 7, 3.0);
+        for method in METHODS {
+            assert_near(&DMatrix::from_element(3, 11, 3.0), &resample(&values, 3, 11, method));
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_nearest_upsampling() {
+        let values = dmatrix![1.0, 2.0; 3.0, 4.0];
+        let expected = dmatrix![
+            1.0, 1.0, 2.0, 2.0;
+            1.0, 1.0, 2.0, 2.0;
+            3.0, 3.0, 4.0, 4.0;
+            3.0, 3.0, 4.0, 4.0
+        ];
+        assert_eq!(expected, resample(&values, 4, 4, ResampleMethod::Nearest));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_bilinear_upsampling() {
+        let values = dmatrix![0.0, 4.0];
+        let expected = dmatrix![0.0, 1.0, 3.0, 4.0];
+        assert_near(&expected, &resample(&values, 1, 4, ResampleMethod::Bilinear));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_shrinking_averages() {
+        // a stripe one cell wide, which sampling every fourth cell would miss or exaggerate
+        let values = DMatrix::from_fn(1, 16, |_, column| if column % 4 == 1 { 4.0 } else { 0.0 });
+        for method in [ResampleMethod::Bilinear, ResampleMethod::Bicubic] {
+            assert_near(&DMatrix::from_element(1, 4, 1.0), &resample(&values, 1, 4, method));
+        }
+        // and unevenly, with each cell counting for as much of it as is covered
+        let values = dmatrix![0.0, 3.0, 6.0];
+        assert_near(&dmatrix![1.0, 5.0], &resample(&values, 1, 2, ResampleMethod::Bilinear));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_empty() {
+        let values = DMatrix::zeros(0, 0);
+        for method in METHODS {
+            assert_eq!(DMatrix::zeros(2, 3), resample(&values, 2, 3, method));
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_bicubic_keeps_ramps_straight() {
+        let values = DMatrix::from_fn(1, 8, |_, column| column as Real);
+        let resampled = resample(&values, 1, 15, ResampleMethod::Bicubic);
+        // away from the clamped edges, a ramp is reproduced exactly
+        let scale = 8.0 / 15.0;
+        for column in 4..11 {
+            let expected = (column as Real + 0.5) * scale - 0.5;
+            assert!((expected - resampled[(0, column)]).abs() < 0.0001);
+        }
+    }
+}
@@ -1,18 +1,19 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
 use crate::dimension::*;
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 #[derive(Debug, Clone)]
 pub struct Screen {
     pub dimension: Dimension
 }
 
-#[wasm_bindgen]
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
 impl Screen {
-    #[wasm_bindgen(constructor)]
-    pub fn new(side_length: f32) -> Screen {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(width: f32, height: f32) -> Screen {
         Screen { 
-            dimension: Dimension { side_length } 
+            dimension: Dimension { width, depth: height } 
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,328 @@
+// Simulation state handed from a Simulation running on a worker to the main thread,
+// through a buffer of 32 bit words which both can see, laid out as:
+//   a header of HEADER_WORDS words, as below
+//   x, y, radius of up to max_balls balls, on screen
+//   depth of water in each cell, row by row
+// The sequence is odd while state is being written, and moves on by 2 for each write,
+// so that a reader can tell whether what it read was torn by a write part way through
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg(feature = "wasm")]
+use crate::Simulation;
+
+const SEQUENCE: usize = 0;
+const MAX_BALLS: usize = 1;
+const NUM_BALLS: usize = 2;
+const WATER_ROWS: usize = 3;
+const WATER_COLUMNS: usize = 4;
+const HEADER_WORDS: usize = 5;
+const BALL_WORDS: usize = 3;
+
+pub trait SharedWords {
+    // atomically, so that values written before a store are seen by a load which sees it
+    fn load(&self, index: usize) -> u32;
+    fn store(&mut self, index: usize, value: u32);
+    fn load_floats(&self, index: usize, values: &mut [f32]);
+    fn store_floats(&mut self, index: usize, values: &[f32]);
+}
+
+// for when there is only one thread, e.g. in tests
+impl SharedWords for Vec<u32> {
+    fn load(&self, index: usize) -> u32 {
+        self[index]
+    }
+
+    fn store(&mut self, index: usize, value: u32) {
+        self[index] = value;
+    }
+
+    fn load_floats(&self, index: usize, values: &mut [f32]) {
+        for (value, word) in values.iter_mut().zip(&self[index..]) {
+            *value = f32::from_bits(*word);
+        }
+    }
+
+    fn store_floats(&mut self, index: usize, values: &[f32]) {
+        for (word, value) in self[index..].iter_mut().zip(values) {
+            *word = value.to_bits();
+        }
+    }
+}
+
+pub fn words_needed(max_balls: usize, water_rows: usize, water_columns: usize) -> usize {
+    HEADER_WORDS + max_balls * BALL_WORDS + water_rows * water_columns
+}
+
+// lays out words, which must be at least words_needed long, with nothing yet written
+pub fn initialise<W: SharedWords>(words: &mut W, max_balls: usize, water_rows: usize, water_columns: usize) {
+    words.store(SEQUENCE, 0);
+    words.store(MAX_BALLS, max_balls as u32);
+    words.store(NUM_BALLS, 0);
+    words.store(WATER_ROWS, water_rows as u32);
+    words.store(WATER_COLUMNS, water_columns as u32);
+}
+
+// ball_state is x, y, radius of each ball, of which any beyond max_balls are left out,
+// and water_depths is row by row
+pub fn write<W: SharedWords>(words: &mut W, ball_state: &[f32], water_depths: &[f32]) {
+    let sequence = words.load(SEQUENCE);
+    words.store(SEQUENCE, sequence.wrapping_add(1));
+
+    let max_balls = words.load(MAX_BALLS) as usize;
+    let num_balls = (ball_state.len() / BALL_WORDS).min(max_balls);
+    words.store(NUM_BALLS, num_balls as u32);
+    words.store_floats(HEADER_WORDS, &ball_state[..num_balls * BALL_WORDS]);
+    let water_cells = (words.load(WATER_ROWS) * words.load(WATER_COLUMNS)) as usize;
+    words.store_floats(HEADER_WORDS + max_balls * BALL_WORDS, &water_depths[..water_cells.min(water_depths.len())]);
+
+    words.store(SEQUENCE, sequence.wrapping_add(2));
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct State {
+    // x, y, radius of each ball
+    pub ball_state: Vec<f32>,
+    // row by row
+    pub water_depths: Vec<f32>
+}
+
+// copies state written since the sequence since into state, returning its sequence, or None,
+// leaving state as it was, if there is nothing new or it was torn by a write.
+// The state is read into scratch first, which is swapped with state once known not to be torn
+pub fn read<W: SharedWords>(words: &W, since: u32, state: &mut State, scratch: &mut State) -> Option<u32> {
+    let sequence = words.load(SEQUENCE);
+    if sequence % 2 == 1 || sequence == since {
+        return None;
+    }
+
+    let max_balls = words.load(MAX_BALLS) as usize;
+    let num_balls = (words.load(NUM_BALLS) as usize).min(max_balls);
+    let water_cells = (words.load(WATER_ROWS) * words.load(WATER_COLUMNS)) as usize;
+    scratch.ball_state.resize(num_balls * BALL_WORDS, 0.0);
+    words.load_floats(HEADER_WORDS, &mut scratch.ball_state);
+    scratch.water_depths.resize(water_cells, 0.0);
+    words.load_floats(HEADER_WORDS + max_balls * BALL_WORDS, &mut scratch.water_depths);
+
+    if words.load(SEQUENCE) != sequence {
+        return None;
+    }
+    std::mem::swap(state, scratch);
+    Some(sequence)
+}
+
+// a SharedArrayBuffer, or an ArrayBuffer for when that isn't available
+#[cfg(feature = "wasm")]
+struct JsWords {
+    header: js_sys::Int32Array,
+    values: js_sys::Float32Array
+}
+
+#[cfg(feature = "wasm")]
+impl JsWords {
+    fn new(buffer: &JsValue) -> JsWords {
+        JsWords {
+            header: js_sys::Int32Array::new(buffer),
+            values: js_sys::Float32Array::new(buffer)
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl SharedWords for JsWords {
+    fn load(&self, index: usize) -> u32 {
+        js_sys::Atomics::load(&self.header, index as u32).unwrap() as u32
+    }
+
+    fn store(&mut self, index: usize, value: u32) {
+        js_sys::Atomics::store(&self.header, index as u32, value as i32).unwrap();
+    }
+
+    fn load_floats(&self, index: usize, values: &mut [f32]) {
+        self.values.subarray(index as u32, (index + values.len()) as u32).copy_to(values);
+    }
+
+    fn store_floats(&mut self, index: usize, values: &[f32]) {
+        self.values.subarray(index as u32, (index + values.len()) as u32).copy_from(values);
+    }
+}
+
+// writes the state of a Simulation after each update, on the worker running it
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub struct StateWriter {
+    words: JsWords
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl StateWriter {
+    // size of the buffer to give to new
+    pub fn byte_length(simulation: &Simulation, max_balls: usize) -> usize {
+        let (water_rows, water_columns) = simulation.water_depths().shape();
+        4 * words_needed(max_balls, water_rows, water_columns)
+    }
+
+    #[wasm_bindgen(constructor)]
+    pub fn new(buffer: &JsValue, simulation: &Simulation, max_balls: usize) -> StateWriter {
+        let mut words = JsWords::new(buffer);
+        let (water_rows, water_columns) = simulation.water_depths().shape();
+        initialise(&mut words, max_balls, water_rows, water_columns);
+        StateWriter { words }
+    }
+
+    pub fn write(&mut self, simulation: &Simulation) {
+        write(&mut self.words, simulation.ball_state_values(), simulation.water_state_values());
+    }
+}
+
+// reads the state of a Simulation running on a worker, on the main thread
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub struct StateReader {
+    words: JsWords,
+    sequence: u32,
+    state: State,
+    scratch: State
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl StateReader {
+    #[wasm_bindgen(constructor)]
+    pub fn new(buffer: &JsValue) -> StateReader {
+        StateReader { words: JsWords::new(buffer), sequence: 0, state: State::default(), scratch: State::default() }
+    }
+
+    // true if there was new state, which is then what ball_state and water_depths show
+    pub fn poll(&mut self) -> bool {
+        match read(&self.words, self.sequence, &mut self.state, &mut self.scratch) {
+            Some(sequence) => {
+                self.sequence = sequence;
+                true
+            },
+            None => false
+        }
+    }
+
+    // as Simulation::ball_state, and likewise only valid until the next call into the reader
+    pub fn ball_state(&self) -> js_sys::Float32Array {
+        unsafe { js_sys::Float32Array::view(&self.state.ball_state) }
+    }
+
+    // row by row, and only valid until the next call into the reader
+    pub fn water_depths(&self) -> js_sys::Float32Array {
+        unsafe { js_sys::Float32Array::view(&self.state.water_depths) }
+    }
+
+    pub fn water_rows(&self) -> usize {
+        self.words.load(WATER_ROWS) as usize
+    }
+
+    pub fn water_columns(&self) -> usize {
+        self.words.load(WATER_COLUMNS) as usize
+    }
+}
+
+#[cfg(test)]
+mod shared_tests {
+    use std::cell::RefCell;
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    fn words(max_balls: usize) -> Vec<u32> {
+        let mut words = vec![0; words_needed(max_balls, 2, 3)];
+        initialise(&mut words, max_balls, 2, 3);
+        words
+    }
+
+    fn state(ball_state: &[f32], water_depths: &[f32]) -> State {
+        State { ball_state: ball_state.to_vec(), water_depths: water_depths.to_vec() }
+    }
+
+    // words which are written to again part way through being read
+    struct TearingWords {
+        words: RefCell<Vec<u32>>,
+        ball_state: Vec<f32>
+    }
+
+    impl SharedWords for TearingWords {
+        fn load(&self, index: usize) -> u32 {
+            self.words.borrow().load(index)
+        }
+
+        fn store(&mut self, index: usize, value: u32) {
+            self.words.get_mut().store(index, value);
+        }
+
+        fn load_floats(&self, index: usize, values: &mut [f32]) {
+            self.words.borrow().load_floats(index, values);
+            write(&mut *self.words.borrow_mut(), &self.ball_state, &[0.0; 6]);
+        }
+
+        fn store_floats(&mut self, index: usize, values: &[f32]) {
+            self.words.get_mut().store_floats(index, values);
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_write_then_read() {
+        let mut words = words(4);
+        let (mut read_state, mut scratch) = (State::default(), State::default());
+        assert_eq!(None, read(&words, 0, &mut read_state, &mut scratch));
+
+        let written_balls = vec![1.0, 2.0, 0.5, 3.0, 4.0, 0.25];
+        let written_water = vec![0.0, 0.1, 0.2, 0.3, 0.4, 0.5];
+        write(&mut words, &written_balls, &written_water);
+        let sequence = read(&words, 0, &mut read_state, &mut scratch).unwrap();
+        assert_eq!(state(&written_balls, &written_water), read_state);
+
+        // nothing new until the next write
+        assert_eq!(None, read(&words, sequence, &mut read_state, &mut scratch));
+        write(&mut words, &written_balls[..3], &written_water);
+        assert!(read(&words, sequence, &mut read_state, &mut scratch).unwrap() > sequence);
+        assert_eq!(written_balls[..3], read_state.ball_state);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_balls_beyond_max_are_left_out() {
+        let mut words = words(1);
+        let (mut read_state, mut scratch) = (State::default(), State::default());
+        write(&mut words, &[1.0, 2.0, 0.5, 3.0, 4.0, 0.25], &[0.0; 6]);
+        read(&words, 0, &mut read_state, &mut scratch).unwrap();
+        assert_eq!(state(&[1.0, 2.0, 0.5], &[0.0; 6]), read_state);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_torn_write_is_not_read() {
+        let mut words = words(1);
+        let (mut read_state, mut scratch) = (State::default(), State::default());
+        write(&mut words, &[1.0, 2.0, 0.5], &[0.0; 6]);
+        // as if part way through the next write
+        let sequence = words.load(SEQUENCE);
+        words.store(SEQUENCE, sequence + 1);
+        assert_eq!(None, read(&words, 0, &mut read_state, &mut scratch));
+        assert_eq!(State::default(), read_state);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_torn_read_leaves_state_unchanged() {
+        let mut words = words(1);
+        let (mut read_state, mut scratch) = (State::default(), State::default());
+        write(&mut words, &[1.0, 2.0, 0.5], &[0.0; 6]);
+        let sequence = read(&words, 0, &mut read_state, &mut scratch).unwrap();
+        let previous = read_state.clone();
+
+        // a write which starts only once the read has begun
+        write(&mut words, &[3.0, 4.0, 0.5], &[0.0; 6]);
+        let tearing = TearingWords { words: RefCell::new(words), ball_state: vec![5.0, 6.0, 0.5] };
+        assert_eq!(None, read(&tearing, sequence, &mut read_state, &mut scratch));
+        assert_eq!(previous, read_state);
+
+        // and the next read, with no write part way through it, sees the latest
+        let words = tearing.words.into_inner();
+        assert!(read(&words, sequence, &mut read_state, &mut scratch).is_some());
+        assert_eq!(vec![5.0, 6.0, 0.5], read_state.ball_state);
+    }
+}
@@ -0,0 +1,343 @@
+// Stitching of z/x/y web-mercator ("slippy map") tiles into a single Terrain
+use std::collections::HashSet;
+use std::f64::consts::PI;
+
+use rapier3d::prelude::*;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::terrain::*;
+
+// radius used by web-mercator
+const EARTH_RADIUS_METRES: f64 = 6_378_137.0;
+
+// how the rows of a grid are spaced in latitude
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    // evenly in web-mercator y, as for slippy map tiles
+    WebMercator,
+    // evenly in latitude, as for DEMs such as GeoTIFF, ESRI ASCII grid and SRTM
+    Equirectangular
+}
+
+impl Projection {
+    // y, which unlike latitude is linear in rows
+    pub fn latitude_to_y(&self, latitude: f64) -> f64 {
+        match self {
+            Projection::WebMercator => latitude_to_mercator_y(latitude),
+            Projection::Equirectangular => latitude
+        }
+    }
+
+    pub fn y_to_latitude(&self, y: f64) -> f64 {
+        match self {
+            Projection::WebMercator => mercator_y_to_latitude(y),
+            Projection::Equirectangular => y
+        }
+    }
+}
+
+// bounds in degrees of longitude (west, east) and latitude (south, north)
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoBounds {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+    pub projection: Projection
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl GeoBounds {
+    // east-west distance, measured along the middle latitude
+    pub fn width_metres(&self) -> f64 {
+        let middle_latitude = ((self.north + self.south) / 2.0).to_radians();
+        (self.east - self.west).to_radians() * EARTH_RADIUS_METRES * middle_latitude.cos()
+    }
+
+    // north-south distance
+    pub fn height_metres(&self) -> f64 {
+        (self.north - self.south).to_radians() * EARTH_RADIUS_METRES
+    }
+}
+
+impl GeoBounds {
+    // the part of these bounds between proportions of the width (from the west) and
+    // height (from the north), where rows are spaced as given by the projection
+    pub fn within(&self, left: f64, top: f64, right: f64, bottom: f64) -> GeoBounds {
+        let north = self.projection.latitude_to_y(self.north);
+        let south = self.projection.latitude_to_y(self.south);
+        let longitude = |proportion: f64| self.west + proportion * (self.east - self.west);
+        let latitude = |proportion: f64| self.projection.y_to_latitude(north + proportion * (south - north));
+        GeoBounds {
+            west: longitude(left),
+            south: latitude(bottom),
+            east: longitude(right),
+            north: latitude(top),
+            projection: self.projection
+        }
+    }
+}
+
+// web-mercator y, which unlike latitude is linear in tile/pixel rows
+pub fn latitude_to_mercator_y(latitude: f64) -> f64 {
+    (PI / 4.0 + latitude.to_radians() / 2.0).tan().ln()
+}
+
+pub fn mercator_y_to_latitude(y: f64) -> f64 {
+    y.sinh().atan().to_degrees()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileCoordinate {
+    pub z: u32,
+    pub x: u32,
+    pub y: u32
+}
+
+// longitude of the west edge of tiles in column x
+pub fn tile_x_to_longitude(z: u32, x: u32) -> f64 {
+    (x as f64) / 2f64.powi(z as i32) * 360.0 - 180.0
+}
+
+// latitude of the north edge of tiles in row y
+pub fn tile_y_to_latitude(z: u32, y: u32) -> f64 {
+    let n = PI * (1.0 - 2.0 * (y as f64) / 2f64.powi(z as i32));
+    mercator_y_to_latitude(n)
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct TileMosaic {
+    encoding: ElevationEncoding,
+    tiles: Vec<(TileCoordinate, Terrain)>
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl TileMosaic {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(encoding: ElevationEncoding) -> TileMosaic {
+        TileMosaic { encoding, tiles: Vec::new() }
+    }
+
+    pub fn add_tile(&mut self, z: u32, x: u32, y: u32, data: Vec<u8>) -> Result<(), String> {
+        let coordinate = TileCoordinate { z, x, y };
+        let tile = Terrain::try_from_png_image(data, self.encoding, 0.0, 0.0)
+            .map_err(|e| format!("tile {:?}: {}", coordinate, e))?;
+        self.tiles.push((coordinate, tile));
+        Ok(())
+    }
+
+    pub fn build(&self) -> Result<Terrain, String> {
+        let (first_coordinate, first_tile) = self.tiles.first().ok_or("no tiles added")?;
+        let z = first_coordinate.z;
+        let (tile_rows, tile_columns) = (first_tile.rows(), first_tile.columns());
+        for (coordinate, tile) in &self.tiles {
+            if coordinate.z != z {
+                return Err(format!("expected all tiles at zoom {} but got {:?}", z, coordinate));
+            }
+            if (tile.rows(), tile.columns()) != (tile_rows, tile_columns) {
+                return Err(format!("expected all tiles to be {}x{} but {:?} is {}x{}",
+                    tile_columns, tile_rows, coordinate, tile.columns(), tile.rows()));
+            }
+        }
+        let min_x = self.tiles.iter().map(|(c, _)| c.x).min().unwrap();
+        let max_x = self.tiles.iter().map(|(c, _)| c.x).max().unwrap();
+        let min_y = self.tiles.iter().map(|(c, _)| c.y).min().unwrap();
+        let max_y = self.tiles.iter().map(|(c, _)| c.y).max().unwrap();
+        let tiles_across = (max_x - min_x + 1) as usize;
+        let tiles_down = (max_y - min_y + 1) as usize;
+        let mut added = HashSet::new();
+        let repeated: Vec<TileCoordinate> = self.tiles.iter().map(|(c, _)| *c)
+            .filter(|c| !added.insert(*c))
+            .collect();
+        if !repeated.is_empty() {
+            return Err(format!("expected each tile once but {:?} were added again", repeated));
+        }
+        let missing: Vec<(u32, u32)> = (min_y..=max_y)
+            .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)))
+            .filter(|&(x, y)| !added.contains(&TileCoordinate { z, x, y }))
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!("expected {}x{} tiles covering x {}..={}, y {}..={} but x, y of {:?} are missing",
+                tiles_across, tiles_down, min_x, max_x, min_y, max_y, missing));
+        }
+
+        let mut elevations = DMatrix::zeros(tiles_down * tile_rows, tiles_across * tile_columns);
+        for (coordinate, tile) in &self.tiles {
+            let start = (
+                (coordinate.y - min_y) as usize * tile_rows,
+                (coordinate.x - min_x) as usize * tile_columns
+            );
+            elevations.view_mut(start, (tile_rows, tile_columns)).copy_from(tile.elevations());
+        }
+
+        let bounds = GeoBounds {
+            west: tile_x_to_longitude(z, min_x),
+            south: tile_y_to_latitude(z, max_y + 1),
+            east: tile_x_to_longitude(z, max_x + 1),
+            north: tile_y_to_latitude(z, min_y),
+            projection: Projection::WebMercator
+        };
+        log::info!("Built {}x{} mosaic of {} tiles, covering {:?}",
+            tiles_across * tile_columns, tiles_down * tile_rows, self.tiles.len(), bounds);
+        Ok(Terrain::from_elevations(elevations).with_bounds(bounds))
+    }
+}
+
+impl TileMosaic {
+    // reads the tiles in x_range, y_range at zoom z from a directory laid out
+    // as a tile server would be, i.e. {directory}/{z}/{x}/{y}.png
+    pub fn from_directory(directory: &std::path::Path, encoding: ElevationEncoding, z: u32,
+        x_range: std::ops::RangeInclusive<u32>, y_range: std::ops::RangeInclusive<u32>) -> std::io::Result<TileMosaic> {
+        let mut mosaic = TileMosaic::new(encoding);
+        for x in x_range {
+            for y in y_range.clone() {
+                let path = directory.join(z.to_string()).join(x.to_string()).join(format!("{}.png", y));
+                mosaic.add_tile(z, x, y, std::fs::read(path)?)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            }
+        }
+        Ok(mosaic)
+    }
+}
+
+#[cfg(test)]
+mod tiles_tests {
+    use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+    use std::io::Cursor;
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    fn assert_near(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 0.0001, "expected {} but was {}", expected, actual);
+    }
+
+    // a 2x2 Terrarium tile, with every pixel at the same elevation
+    fn tile(elevation: u8) -> Vec<u8> {
+        let pixel = Rgba([128, elevation, 0, u8::MAX]);
+        let image_buffer: RgbaImage = ImageBuffer::from_pixel(2, 2, pixel);
+        let image = DynamicImage::ImageRgba8(image_buffer);
+        let mut cursor = Cursor::new(Vec::new());
+        image.write_to(&mut cursor, image::ImageFormat::Png).unwrap();
+        cursor.get_ref().to_owned()
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_tile_edges() {
+        assert_near(-180.0, tile_x_to_longitude(0, 0));
+        assert_near(180.0, tile_x_to_longitude(0, 1));
+        assert_near(85.0511, tile_y_to_latitude(0, 0));
+        assert_near(-85.0511, tile_y_to_latitude(0, 1));
+        assert_near(0.0, tile_x_to_longitude(1, 1));
+        assert_near(0.0, tile_y_to_latitude(1, 1));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_mercator_round_trip() {
+        for latitude in [-80.0, -45.0, 0.0, 12.5, 55.9533, 85.0] {
+            assert_near(latitude, mercator_y_to_latitude(latitude_to_mercator_y(latitude)));
+        }
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_size_in_metres() {
+        // one degree at the equator is ~111km in both directions
+        let bounds = GeoBounds { west: 0.0, south: -0.5, east: 1.0, north: 0.5, projection: Projection::Equirectangular };
+        assert!((bounds.width_metres() - 111_319.0).abs() < 1.0);
+        assert!((bounds.height_metres() - 111_319.0).abs() < 1.0);
+        // but only half as wide at 60 degrees
+        let bounds = GeoBounds { west: 0.0, south: 59.5, east: 1.0, north: 60.5, projection: Projection::Equirectangular };
+        assert!((bounds.width_metres() - 55_660.0).abs() < 1.0);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_within() {
+        // a whole zoom 1 tile is a quarter of the zoom 0 tile
+        let world = GeoBounds {
+            west: tile_x_to_longitude(0, 0),
+            south: tile_y_to_latitude(0, 1),
+            east: tile_x_to_longitude(0, 1),
+            north: tile_y_to_latitude(0, 0),
+            projection: Projection::WebMercator
+        };
+        let quarter = world.within(0.5, 0.5, 1.0, 1.0);
+        assert_near(tile_x_to_longitude(1, 1), quarter.west);
+        assert_near(tile_x_to_longitude(1, 2), quarter.east);
+        assert_near(tile_y_to_latitude(1, 1), quarter.north);
+        assert_near(tile_y_to_latitude(1, 2), quarter.south);
+        assert_eq!(world, world.within(0.0, 0.0, 1.0, 1.0));
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_build() {
+        let mut mosaic = TileMosaic::new(ElevationEncoding::Terrarium);
+        mosaic.add_tile(2, 2, 1, tile(1)).unwrap();
+        mosaic.add_tile(2, 1, 1, tile(0)).unwrap();
+        mosaic.add_tile(2, 1, 2, tile(2)).unwrap();
+        mosaic.add_tile(2, 2, 2, tile(3)).unwrap();
+
+        let terrain = mosaic.build().unwrap();
+
+        assert_eq!(4, terrain.width);
+        assert_eq!(4, terrain.height);
+        let expected = nalgebra::dmatrix![
+            0.0, 0.0, 1.0, 1.0;
+            0.0, 0.0, 1.0, 1.0;
+            2.0, 2.0, 3.0, 3.0;
+            2.0, 2.0, 3.0, 3.0
+        ];
+        assert_eq!(&expected, terrain.elevations());
+        let bounds = terrain.bounds().unwrap();
+        assert_near(-90.0, bounds.west);
+        assert_near(90.0, bounds.east);
+        assert_near(tile_y_to_latitude(2, 1), bounds.north);
+        assert_near(tile_y_to_latitude(2, 3), bounds.south);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_build_rejects_gaps() {
+        let mut mosaic = TileMosaic::new(ElevationEncoding::Terrarium);
+        mosaic.add_tile(2, 0, 0, tile(0)).unwrap();
+        mosaic.add_tile(2, 1, 1, tile(0)).unwrap();
+        let error = mosaic.build().err().unwrap();
+        assert!(error.contains("[(1, 0), (0, 1)]"), "{}", error);
+        assert!(TileMosaic::new(ElevationEncoding::Terrarium).build().is_err());
+
+        // as many tiles as a complete mosaic, but one twice over
+        let mut mosaic = TileMosaic::new(ElevationEncoding::Terrarium);
+        mosaic.add_tile(2, 0, 0, tile(0)).unwrap();
+        mosaic.add_tile(2, 0, 0, tile(0)).unwrap();
+        mosaic.add_tile(2, 1, 1, tile(0)).unwrap();
+        mosaic.add_tile(2, 1, 1, tile(0)).unwrap();
+        assert!(mosaic.build().is_err());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_add_tile_rejects_bad_data() {
+        let mut mosaic = TileMosaic::new(ElevationEncoding::Terrarium);
+        assert!(mosaic.add_tile(2, 0, 0, vec![1, 2, 3]).is_err());
+        assert!(mosaic.build().is_err());
+    }
+
+    #[test]
+    fn test_from_directory() {
+        let directory = std::env::temp_dir().join(format!("tiles_tests_{}", std::process::id()));
+        for (x, y, elevation) in [(3, 5, 0), (4, 5, 1)] {
+            let tile_directory = directory.join("3").join(x.to_string());
+            std::fs::create_dir_all(&tile_directory).unwrap();
+            std::fs::write(tile_directory.join(format!("{}.png", y)), tile(elevation)).unwrap();
+        }
+
+        let mosaic = TileMosaic::from_directory(&directory, ElevationEncoding::Terrarium, 3, 3..=4, 5..=5).unwrap();
+        let terrain = mosaic.build().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(4, terrain.width);
+        assert_eq!(2, terrain.height);
+        assert_eq!(&nalgebra::dmatrix![0.0, 0.0, 1.0, 1.0; 0.0, 0.0, 1.0, 1.0], terrain.elevations());
+        assert_near(tile_x_to_longitude(3, 3), terrain.bounds().unwrap().west);
+    }
+}
@@ -0,0 +1,78 @@
+// Turns the wall-clock time between updates into a whole number of fixed-size steps,
+// carrying over whatever is left for next time, so that simulated time keeps pace with
+// wall-clock time however often updates happen
+use rapier3d::prelude::*;
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixedTimestep {
+    // seconds per step
+    dt: Real,
+    // most steps to take in one update, so that falling behind, e.g. in a throttled tab,
+    // slows the simulation down rather than leaving it ever further behind
+    max_steps: usize,
+    // seconds not yet stepped, always less than dt after an update
+    accumulated: Real
+}
+
+impl FixedTimestep {
+    pub fn new(dt: Real, max_steps: usize) -> FixedTimestep {
+        FixedTimestep { dt, max_steps, accumulated: 0.0 }
+    }
+
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+
+    // the number of steps to take to cover another elapsed seconds
+    pub fn advance(&mut self, elapsed: Real) -> usize {
+        self.accumulated += elapsed.max(0.0);
+        let steps = (self.accumulated / self.dt).floor() as usize;
+        if steps > self.max_steps {
+            // drop the time that can't be caught up on
+            self.accumulated = 0.0;
+            return self.max_steps;
+        }
+        self.accumulated -= steps as Real * self.dt;
+        steps
+    }
+
+    // how far wall-clock time is between the last step and the next, from 0 to 1,
+    // for interpolating between the states before and after the last step
+    pub fn alpha(&self) -> Real {
+        (self.accumulated / self.dt).clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod timestep_tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_faster_updates_than_steps() {
+        // as on a 120Hz display
+        let mut timestep = FixedTimestep::new(1.0 / 60.0, 4);
+        let steps: Vec<usize> = (0..120).map(|_| timestep.advance(1.0 / 120.0)).collect();
+        assert_eq!(60, steps.iter().sum::<usize>());
+        assert!(steps.iter().all(|steps| *steps <= 1));
+        assert!(timestep.alpha() < 0.01 || timestep.alpha() > 0.99);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_slower_updates_than_steps() {
+        let mut timestep = FixedTimestep::new(1.0 / 60.0, 4);
+        assert_eq!(2, timestep.advance(1.0 / 30.0 + 0.001));
+        assert!((timestep.alpha() - 0.06).abs() < 0.001);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_long_pause_is_capped() {
+        let mut timestep = FixedTimestep::new(1.0 / 60.0, 4);
+        assert_eq!(4, timestep.advance(10.0));
+        // and not caught up on afterwards
+        assert_eq!(0, timestep.advance(0.001));
+        assert_eq!(0, timestep.advance(-1.0));
+    }
+}
@@ -0,0 +1,67 @@
+// Trade-offs between how accurately balls are simulated and how many can be simulated
+use rapier3d::prelude::*;
+use serde::{Serialize, Deserialize};
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Tuning {
+    // continuous collision detection for every ball, so that fast balls can't pass through
+    // the terrain or each other, at the cost of extra work for every ball
+    pub ccd: bool,
+    // solver iterations per step, fewer of which leave contacts less settled
+    pub solver_iterations: usize,
+    // whether balls at rest may sleep, so as not to be simulated until something disturbs them
+    pub sleeping: bool,
+    // seconds a ball must be at rest before it sleeps
+    pub time_until_sleep: Real
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Tuning {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(ccd: bool, solver_iterations: usize, sleeping: bool, time_until_sleep: Real) -> Tuning {
+        Tuning { ccd, solver_iterations, sleeping, time_until_sleep }
+    }
+
+    // CCD on every ball and rapier's 4 solver iterations, for up to a few hundred balls,
+    // which may be thrown fast enough to tunnel through the terrain without CCD
+    pub fn accurate() -> Tuning {
+        Tuning::new(true, 4, true, RigidBodyActivation::default_time_until_sleep())
+    }
+
+    // for thousands of balls, which are mostly in piles rather than flying about
+    pub fn many_bodies() -> Tuning {
+        Tuning::new(false, 2, true, 0.5)
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Tuning {
+        Tuning::accurate()
+    }
+}
+
+impl Tuning {
+    pub fn apply_to_parameters(&self, integration_parameters: &mut IntegrationParameters) {
+        integration_parameters.max_velocity_iterations = self.solver_iterations;
+        // keeping rapier's ratio of friction to velocity iterations
+        integration_parameters.max_velocity_friction_iterations = 2 * self.solver_iterations;
+    }
+
+    pub fn apply_to_body(&self, body: &mut RigidBody) {
+        body.enable_ccd(self.ccd);
+        let activation = body.activation_mut();
+        if self.sleeping {
+            activation.linear_threshold = RigidBodyActivation::default_linear_threshold();
+            activation.angular_threshold = RigidBodyActivation::default_angular_threshold();
+            activation.time_until_sleep = self.time_until_sleep;
+        } else {
+            let cannot_sleep = RigidBodyActivation::cannot_sleep();
+            activation.linear_threshold = cannot_sleep.linear_threshold;
+            activation.angular_threshold = cannot_sleep.angular_threshold;
+            body.wake_up(true);
+        }
+    }
+}
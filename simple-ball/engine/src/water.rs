@@ -0,0 +1,286 @@
+use rapier3d::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::terrain::*;
+
+const GRAVITY: Real = 9.81;
+const MIN_DEPTH: Real = 0.0001;
+// proportion of flux lost per second, so that water eventually settles
+const FRICTION: Real = 0.5;
+
+#[derive(Serialize, Deserialize)]
+struct Flow {
+    row: usize,
+    column: usize,
+    // volume per second
+    rate: Real
+}
+
+// outflow from each cell through "virtual pipes" to its four neighbours
+#[derive(Serialize, Deserialize)]
+struct Flux {
+    left: DMatrix<Real>,
+    right: DMatrix<Real>,
+    up: DMatrix<Real>,
+    down: DMatrix<Real>
+}
+
+impl Flux {
+    fn zeros(rows: usize, columns: usize) -> Flux {
+        Flux {
+            left: DMatrix::zeros(rows, columns),
+            right: DMatrix::zeros(rows, columns),
+            up: DMatrix::zeros(rows, columns),
+            down: DMatrix::zeros(rows, columns)
+        }
+    }
+
+    fn total(&self, index: (usize, usize)) -> Real {
+        self.left[index] + self.right[index] + self.up[index] + self.down[index]
+    }
+}
+
+// A shallow-water solver using the "virtual pipes" model, where water
+// flows between neighbouring cells in proportion to the difference
+// in their water surface heights.
+// All grids are stored as for Terrain elevations, i.e.
+// x = columns, y = rows, where x, y is in screen space
+#[derive(Serialize, Deserialize)]
+pub struct ShallowWater {
+    bed: DMatrix<Real>,
+    depth: DMatrix<Real>,
+    velocity_x: DMatrix<Real>,
+    velocity_y: DMatrix<Real>,
+    flux: Flux,
+    cell_size: Real,
+    sources: Vec<Flow>,
+    sinks: Vec<Flow>
+}
+
+impl ShallowWater {
+    pub fn new(terrain: &Terrain, cell_size: Real) -> ShallowWater {
+        let (rows, columns) = (terrain.rows(), terrain.columns());
+        ShallowWater {
+            bed: terrain.elevations().clone(),
+            depth: DMatrix::zeros(rows, columns),
+            velocity_x: DMatrix::zeros(rows, columns),
+            velocity_y: DMatrix::zeros(rows, columns),
+            flux: Flux::zeros(rows, columns),
+            cell_size,
+            sources: Vec::new(),
+            sinks: Vec::new()
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.bed.nrows()
+    }
+
+    pub fn columns(&self) -> usize {
+        self.bed.ncols()
+    }
+
+    pub fn add_source(&mut self, row: usize, column: usize, rate: Real) {
+        self.sources.push(Flow { row, column, rate });
+    }
+
+    pub fn add_sink(&mut self, row: usize, column: usize, rate: Real) {
+        self.sinks.push(Flow { row, column, rate });
+    }
+
+    pub fn depths(&self) -> &DMatrix<Real> {
+        &self.depth
+    }
+
+    pub fn velocity(&self, row: usize, column: usize) -> (Real, Real) {
+        (self.velocity_x[(row, column)], self.velocity_y[(row, column)])
+    }
+
+    pub fn total_volume(&self) -> Real {
+        self.depth.sum() * self.cell_area()
+    }
+
+    pub fn step(&mut self, dt: Real) {
+        self.apply_flows(dt);
+        self.update_flux(dt);
+        self.update_depth_and_velocity(dt);
+    }
+
+    fn cell_area(&self) -> Real {
+        self.cell_size * self.cell_size
+    }
+
+    fn surface(&self, index: (usize, usize)) -> Real {
+        self.bed[index] + self.depth[index]
+    }
+
+    fn neighbours(&self, row: usize, column: usize) -> [Option<(usize, usize)>; 4] {
+        let left = if column > 0 { Some((row, column - 1)) } else { None };
+        let right = if column + 1 < self.columns() { Some((row, column + 1)) } else { None };
+        let up = if row > 0 { Some((row - 1, column)) } else { None };
+        let down = if row + 1 < self.rows() { Some((row + 1, column)) } else { None };
+        [left, right, up, down]
+    }
+
+    fn apply_flows(&mut self, dt: Real) {
+        let cell_area = self.cell_area();
+        for source in &self.sources {
+            self.depth[(source.row, source.column)] += source.rate * dt / cell_area;
+        }
+        for sink in &self.sinks {
+            let depth = &mut self.depth[(sink.row, sink.column)];
+            *depth = (*depth - sink.rate * dt / cell_area).max(0.0);
+        }
+    }
+
+    fn update_flux(&mut self, dt: Real) {
+        // pipes have a cross-section of cell_size^2 and length of cell_size
+        let pipe = dt * self.cell_size * GRAVITY;
+        let damping = (1.0 - FRICTION * dt).max(0.0);
+        let cell_area = self.cell_area();
+        for row in 0..self.rows() {
+            for column in 0..self.columns() {
+                let index = (row, column);
+                let surface = self.surface(index);
+                let outflow = |current: Real, neighbour: Option<(usize, usize)>| {
+                    match neighbour {
+                        Some(neighbour) => (damping * current + pipe * (surface - self.surface(neighbour))).max(0.0),
+                        None => 0.0
+                    }
+                };
+                let [left, right, up, down] = self.neighbours(row, column);
+                let mut fluxes = [
+                    outflow(self.flux.left[index], left),
+                    outflow(self.flux.right[index], right),
+                    outflow(self.flux.up[index], up),
+                    outflow(self.flux.down[index], down)
+                ];
+
+                // never let more water leave than the cell holds
+                let total_outflow = fluxes.iter().sum::<Real>() * dt;
+                let volume = self.depth[index] * cell_area;
+                if total_outflow > volume {
+                    let scale = volume / total_outflow;
+                    for flux in fluxes.iter_mut() {
+                        *flux *= scale;
+                    }
+                }
+
+                let [left, right, up, down] = fluxes;
+                self.flux.left[index] = left;
+                self.flux.right[index] = right;
+                self.flux.up[index] = up;
+                self.flux.down[index] = down;
+            }
+        }
+    }
+
+    fn update_depth_and_velocity(&mut self, dt: Real) {
+        let cell_area = self.cell_area();
+        let flux = &self.flux;
+        let (rows, columns) = (self.rows(), self.columns());
+        let mut depth = self.depth.clone();
+        for row in 0..rows {
+            for column in 0..columns {
+                let index = (row, column);
+                let [left, right, up, down] = self.neighbours(row, column);
+                let from_left = left.map_or(0.0, |n| flux.right[n]);
+                let from_right = right.map_or(0.0, |n| flux.left[n]);
+                let from_up = up.map_or(0.0, |n| flux.down[n]);
+                let from_down = down.map_or(0.0, |n| flux.up[n]);
+
+                let inflow = from_left + from_right + from_up + from_down;
+                let outflow = flux.total(index);
+                let previous_depth = self.depth[index];
+                let next_depth = (previous_depth + dt * (inflow - outflow) / cell_area).max(0.0);
+                depth[index] = next_depth;
+
+                let average_depth = (previous_depth + next_depth) / 2.0;
+                if average_depth > MIN_DEPTH {
+                    let through_x = (from_left - flux.left[index] + flux.right[index] - from_right) / 2.0;
+                    let through_y = (from_up - flux.up[index] + flux.down[index] - from_down) / 2.0;
+                    self.velocity_x[index] = through_x / (self.cell_size * average_depth);
+                    self.velocity_y[index] = through_y / (self.cell_size * average_depth);
+                } else {
+                    self.velocity_x[index] = 0.0;
+                    self.velocity_y[index] = 0.0;
+                }
+            }
+        }
+        self.depth = depth;
+    }
+}
+
+#[cfg(test)]
+mod water_tests {
+    use wasm_bindgen_test::*;
+
+    use super::*;
+
+    fn flat_terrain() -> Terrain {
+        Terrain::from_elevations(DMatrix::zeros(5, 5))
+    }
+
+    fn sloped_terrain() -> Terrain {
+        // high on the left, low on the right
+        Terrain::from_elevations(DMatrix::from_fn(5, 5, |_row, column| {
+            (4 - column) as Real
+        }))
+    }
+
+    fn assert_near(expected: Real, actual: Real) {
+        assert!((expected - actual).abs() < 0.001, "expected {} but was {}", expected, actual);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_source_adds_volume() {
+        let mut water = ShallowWater::new(&flat_terrain(), 1.0);
+        water.add_source(2, 2, 3.0);
+        for _ in 0..10 {
+            water.step(0.1);
+        }
+        assert_near(3.0, water.total_volume());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_sink_removes_volume() {
+        let mut water = ShallowWater::new(&flat_terrain(), 1.0);
+        water.add_source(2, 2, 1.0);
+        water.add_sink(2, 2, 1.0);
+        for _ in 0..10 {
+            water.step(0.1);
+        }
+        assert_near(0.0, water.total_volume());
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_water_spreads_and_is_conserved() {
+        let mut water = ShallowWater::new(&flat_terrain(), 1.0);
+        water.add_source(2, 2, 10.0);
+        water.step(0.1);
+        water.sources.clear();
+        for _ in 0..500 {
+            water.step(0.05);
+        }
+        assert_near(1.0, water.total_volume());
+        let depths = water.depths();
+        assert_near(depths[(2, 2)], depths[(0, 0)]);
+        assert_near(depths[(2, 2)], depths[(4, 4)]);
+    }
+
+    #[wasm_bindgen_test(unsupported = test)]
+    fn test_water_flows_downhill() {
+        let mut water = ShallowWater::new(&sloped_terrain(), 1.0);
+        water.add_source(2, 0, 1.0);
+        water.step(0.1);
+        water.sources.clear();
+        let (velocity_x, _) = water.velocity(2, 0);
+        assert!(velocity_x > 0.0);
+        for _ in 0..500 {
+            water.step(0.05);
+        }
+        let depths = water.depths();
+        assert!(depths.column(4).sum() > depths.column(0).sum());
+        assert_near(0.1, water.total_volume());
+    }
+}
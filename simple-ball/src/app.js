@@ -1,8 +1,12 @@
 import init, {
   Simulation,
   Screen,
-  Terrain,
+  Placement,
+  BallMix,
+  BallKind,
 } from "../engine/pkg/simple_ball_engine.js";
+import { prepareTerrain } from "./terrain.js";
+import { RemoteSimulation } from "./remote.js";
 
 console.log("Running");
 
@@ -135,6 +139,10 @@ function registerCanvasForceSensor(canvas) {
       -1.0 * (canvas_y_proportion * 2.0 - 1.0) * sensorModel.force.max;
   };
   canvas.addEventListener("pointerdown", (event) => {
+    if (event.shiftKey || event.altKey) {
+      // pouring, see bindPouring and bindWaterSources
+      return;
+    }
     decideForceFn(event);
     sensorModel.force.apply = true;
   });
@@ -154,12 +162,22 @@ function draw(sim, sensorModel, terrain, canvas) {
 
   context.drawImage(terrain, 0, 0, width, height);
 
-  context.fillStyle = "blue";
-  sim.iter_ball_positions((x, y, ballRadius) => {
+  drawWater(sim, context, width, height);
+
+  // x, y, radius of each ball in turn, and the catchment each is in to colour them by,
+  // read straight out of wasm memory
+  const balls = sim.ball_state();
+  const catchments = sim.ball_catchments();
+  for (let ball = 0; ball < balls.length / 3; ball++) {
+    const x = balls[ball * 3];
+    const y = balls[ball * 3 + 1];
+    const ballRadius = balls[ball * 3 + 2];
+    const catchment = catchments[ball] ?? 0;
+    context.fillStyle = `hsl(${(catchment * 137.5) % 360}, 80%, 45%)`;
     context.beginPath();
     context.arc(x, y, ballRadius, 0, 2 * Math.PI);
     context.fill();
-  });
+  }
 
   context.beginPath();
   const max_size_x = (0.8 * width) / 2.0;
@@ -203,6 +221,32 @@ function draw(sim, sensorModel, terrain, canvas) {
   }
 }
 
+// depth at which water is drawn fully opaque, in metres
+const opaqueWaterDepth = 1.0;
+const waterCanvas = document.createElement("canvas");
+
+function drawWater(sim, context, width, height) {
+  const rows = sim.water_rows();
+  const columns = sim.water_columns();
+  const depths = sim.water_state();
+  if (depths.length !== rows * columns) {
+    return;
+  }
+  waterCanvas.width = columns;
+  waterCanvas.height = rows;
+  const waterContext = waterCanvas.getContext("2d");
+  const image = waterContext.createImageData(columns, rows);
+  for (let cell = 0; cell < depths.length; cell++) {
+    const opacity = Math.min(1.0, depths[cell] / opaqueWaterDepth);
+    image.data[cell * 4] = 30;
+    image.data[cell * 4 + 1] = 90;
+    image.data[cell * 4 + 2] = 220;
+    image.data[cell * 4 + 3] = Math.round(opacity * 255);
+  }
+  waterContext.putImageData(image, 0, 0);
+  context.drawImage(waterCanvas, 0, 0, width, height);
+}
+
 async function loadTerrainBlob() {
   const terrain_path =
     "./src/data/guide-access-elevation-data-example-response-960-5d3c885c50fbb3feea782f36bf241b87.png";
@@ -213,6 +257,90 @@ async function loadTerrainBlob() {
   return blob;
 }
 
+function bindSnapshotButtons(sim) {
+  const key = "snapshot";
+  document.getElementById("save").onclick = async () => {
+    // a promise when the simulation is on a worker
+    const snapshot = await sim.snapshot();
+    let binary = "";
+    for (let i = 0; i < snapshot.length; i++) {
+      binary += String.fromCharCode(snapshot[i]);
+    }
+    window.localStorage.setItem(key, btoa(binary));
+  };
+  document.getElementById("restore").onclick = () => {
+    const saved = window.localStorage.getItem(key);
+    if (saved === null) {
+      console.log("no snapshot saved");
+      return;
+    }
+    const snapshot = Uint8Array.from(atob(saved), (c) => c.charCodeAt(0));
+    sim.restore(snapshot);
+  };
+}
+
+// shift and press on the canvas to pour balls there, for as long as it is pressed
+function bindPouring(sim, canvas) {
+  const ballsPerSecond = 20;
+  const ballRadius = 3;
+  var emitter = undefined;
+  canvas.addEventListener("pointerdown", (event) => {
+    if (!event.shiftKey) {
+      return;
+    }
+    event.preventDefault();
+    const rect = canvas.getBoundingClientRect();
+    emitter = sim.add_emitter(
+      event.clientX - rect.left,
+      event.clientY - rect.top,
+      ballsPerSecond,
+      ballRadius
+    );
+  });
+  canvas.addEventListener("pointerup", () => {
+    if (emitter !== undefined) {
+      sim.remove_emitter(emitter);
+      emitter = undefined;
+    }
+  });
+}
+
+// alt and click on the canvas to add a spring there, which flows until reloaded
+function bindWaterSources(sim, canvas) {
+  const cubicMetresPerSecond = 5;
+  canvas.addEventListener("pointerdown", (event) => {
+    if (!event.altKey) {
+      return;
+    }
+    event.preventDefault();
+    const rect = canvas.getBoundingClientRect();
+    sim.add_water_source(
+      event.clientX - rect.left,
+      event.clientY - rect.top,
+      cubicMetresPerSecond
+    );
+  });
+}
+
+function chooseCrop() {
+  // use ?crop=x,y,width,height to zoom into part of the terrain image
+  return new URLSearchParams(window.location.search).get("crop");
+}
+
+function useWorker() {
+  // use ?worker to run the simulation on a worker, apart from drawing
+  return new URLSearchParams(window.location.search).has("worker");
+}
+
+function chooseSeed() {
+  // use ?seed=... to reproduce a previous run
+  const param = new URLSearchParams(window.location.search).get("seed");
+  if (param !== null) {
+    return BigInt(param);
+  }
+  return BigInt(Math.floor(Math.random() * Number.MAX_SAFE_INTEGER));
+}
+
 async function app() {
   console.log("starting init ...");
   await init();
@@ -221,13 +349,8 @@ async function app() {
   const terrainBlob = await loadTerrainBlob();
   const terrainBitmap = await createImageBitmap(terrainBlob);
   const terrainBuffer = new Uint8Array(await terrainBlob.arrayBuffer());
-  const terrain = Terrain.from_png_terrain_image(terrainBuffer)
-    // .halfed()
-    // .halfed()
-    // .halfed()
-    // .halfed()
-    .halfed()
-    .halfed();
+  const crop = chooseCrop();
+  const terrain = prepareTerrain(terrainBuffer, crop);
   const grayscaleHeightBuffer = terrain.as_grayscale_height_image();
   const grayscaleHeightBlob = new Blob([grayscaleHeightBuffer], {
     type: "image/png",
@@ -236,11 +359,35 @@ async function app() {
 
   const canvas = document.getElementById("canvas");
 
-  const side_length = canvas.width; // assume width = height
-  const screen = new Screen(side_length);
+  // keep the terrain's aspect ratio, within the canvas' original width
+  canvas.height = Math.round((canvas.width * terrain.height) / terrain.width);
+  const screen = new Screen(canvas.width, canvas.height);
   //   const num_balls = 100;
   const num_balls = 200;
-  const sim = new Simulation(num_balls, terrain, screen);
+  const seed = chooseSeed();
+  console.log("seed", seed);
+  const verticalExaggeration = undefined; // i.e. normalised heights
+  const placement = Placement.low_biased();
+  const mix = BallMix.only(BallKind.ball());
+  const sim = useWorker()
+    ? await RemoteSimulation.start({
+        terrain: terrainBuffer,
+        crop,
+        width: canvas.width,
+        height: canvas.height,
+        numBalls: num_balls,
+        seed,
+        maxBalls: 5000,
+      })
+    : new Simulation(
+        num_balls,
+        terrain,
+        screen,
+        seed,
+        verticalExaggeration,
+        placement,
+        mix
+      );
 
   var sensorModel = registerCanvasForceSensor(canvas);
   document.getElementById("enable").onclick = async () => {
@@ -254,6 +401,10 @@ async function app() {
     }
   };
 
+  bindSnapshotButtons(sim);
+  bindPouring(sim, canvas);
+  bindWaterSources(sim, canvas);
+
   var start = undefined;
   var lastUpdate = undefined;
   function animate(timestamp) {
@@ -0,0 +1,121 @@
+import { StateReader } from "../engine/pkg/simple_ball_engine.js";
+
+// Stands in for a Simulation, for one running on a worker (see worker.js).
+// The worker steps at its own rate, and update only picks up the latest state
+// it has written, so the frame rate and simulation rate are independent
+export class RemoteSimulation {
+  // options are terrain (the bytes of a terrain PNG), crop, width, height,
+  // numBalls, seed and maxBalls, the most balls whose state will be shown
+  static start(options) {
+    const worker = new Worker(new URL("./worker.js", import.meta.url), {
+      type: "module",
+    });
+    const sim = new RemoteSimulation(worker);
+    return new Promise((resolve) => {
+      sim.onStarted = (shared) => {
+        console.log("worker started, sharing memory:", shared);
+        resolve(sim);
+      };
+      worker.postMessage({ type: "start", ...options });
+    });
+  }
+
+  constructor(worker) {
+    this.worker = worker;
+    this.reader = undefined;
+    this.force = { x: 0.0, y: 0.0 };
+    this.nextEmitter = 0;
+    this.snapshotRequests = [];
+    worker.onmessage = (event) => this.receive(event.data);
+  }
+
+  receive(message) {
+    switch (message.type) {
+      case "started":
+        this.onStarted(message.shared);
+        break;
+      case "state":
+        // a SharedArrayBuffer once, or a fresh copy of the state every step
+        if (this.reader !== undefined) {
+          this.reader.free();
+        }
+        this.reader = new StateReader(message.buffer);
+        break;
+      case "snapshot":
+        this.snapshotRequests.shift()(message.snapshot);
+        break;
+    }
+  }
+
+  set_force(x, y) {
+    if (x !== this.force.x || y !== this.force.y) {
+      this.force = { x, y };
+      this.worker.postMessage({ type: "force", x, y });
+    }
+  }
+
+  update(_elapsedSinceLastUpdate) {
+    if (this.reader !== undefined) {
+      this.reader.poll();
+    }
+  }
+
+  ball_state() {
+    if (this.reader === undefined) {
+      return new Float32Array(0);
+    }
+    return this.reader.ball_state();
+  }
+
+  water_rows() {
+    return this.reader === undefined ? 0 : this.reader.water_rows();
+  }
+
+  water_columns() {
+    return this.reader === undefined ? 0 : this.reader.water_columns();
+  }
+
+  water_state() {
+    if (this.reader === undefined) {
+      return new Float32Array(0);
+    }
+    return this.reader.water_depths();
+  }
+
+  add_water_source(x, y, rate) {
+    this.worker.postMessage({ type: "add_water_source", x, y, rate });
+  }
+
+  // catchments aren't written by the worker
+  ball_catchments() {
+    return new Uint32Array(0);
+  }
+
+  add_emitter(x, y, ballsPerSecond, radius) {
+    const id = this.nextEmitter++;
+    this.worker.postMessage({
+      type: "add_emitter",
+      id,
+      x,
+      y,
+      ballsPerSecond,
+      radius,
+    });
+    return id;
+  }
+
+  remove_emitter(id) {
+    this.worker.postMessage({ type: "remove_emitter", id });
+  }
+
+  snapshot() {
+    return new Promise((resolve) => {
+      this.snapshotRequests.push(resolve);
+      this.worker.postMessage({ type: "snapshot" });
+    });
+  }
+
+  restore(snapshot) {
+    this.worker.postMessage({ type: "restore", snapshot });
+  }
+}
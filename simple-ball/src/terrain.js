@@ -0,0 +1,22 @@
+import { Terrain } from "../engine/pkg/simple_ball_engine.js";
+
+// The terrain to simulate, from the bytes of a terrain PNG, cropped to crop if given
+// as "x,y,width,height". Used by both app.js and worker.js, so that they agree on it
+export function prepareTerrain(terrainBuffer, crop) {
+  let terrain = Terrain.from_png_terrain_image(terrainBuffer);
+  if (crop !== null) {
+    const [x, y, width, height] = crop.split(",").map((n) => parseInt(n, 10));
+    terrain = terrain.crop(x, y, width, height);
+    const source = terrain.source_region();
+    console.log("cropped to", source.x, source.y, source.width, source.height);
+  }
+  return (
+    terrain
+      // .halfed()
+      // .halfed()
+      // .halfed()
+      // .halfed()
+      .halfed()
+      .halfed()
+  );
+}
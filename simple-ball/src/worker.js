@@ -0,0 +1,103 @@
+import init, {
+  Simulation,
+  Screen,
+  Placement,
+  BallMix,
+  BallKind,
+  StateWriter,
+} from "../engine/pkg/simple_ball_engine.js";
+import { prepareTerrain } from "./terrain.js";
+
+// Runs a Simulation off the main thread, stepping it at its own rate and writing
+// its state after every step for a RemoteSimulation (see remote.js) to read.
+// The state is written into a SharedArrayBuffer when the page is cross-origin
+// isolated, and otherwise into a buffer which is copied and transferred each step
+
+const stepsPerSecond = 60;
+const stepMillis = 1000 / stepsPerSecond;
+
+var sim = undefined;
+var writer = undefined;
+var buffer = undefined;
+// RemoteSimulation's emitter ids, to those of sim
+const emitters = new Map();
+
+function publish() {
+  writer.write(sim);
+  if (buffer instanceof ArrayBuffer) {
+    const copy = buffer.slice(0);
+    self.postMessage({ type: "state", buffer: copy }, [copy]);
+  }
+}
+
+async function start(message) {
+  await init();
+  const terrain = prepareTerrain(message.terrain, message.crop);
+  const screen = new Screen(message.width, message.height);
+  sim = new Simulation(
+    message.numBalls,
+    terrain,
+    screen,
+    message.seed,
+    undefined, // i.e. normalised heights
+    Placement.low_biased(),
+    BallMix.only(BallKind.ball())
+  );
+
+  const byteLength = StateWriter.byte_length(sim, message.maxBalls);
+  buffer = self.crossOriginIsolated
+    ? new SharedArrayBuffer(byteLength)
+    : new ArrayBuffer(byteLength);
+  writer = new StateWriter(buffer, sim, message.maxBalls);
+  if (buffer instanceof SharedArrayBuffer) {
+    // once only, as from now on both sides see every write
+    self.postMessage({ type: "state", buffer });
+  }
+  publish();
+  self.postMessage({ type: "started", shared: self.crossOriginIsolated });
+
+  // timers are only roughly on time, so step for however long it has really been
+  let lastUpdate = performance.now();
+  setInterval(() => {
+    const now = performance.now();
+    sim.update(now - lastUpdate);
+    lastUpdate = now;
+    publish();
+  }, stepMillis);
+}
+
+self.onmessage = async (event) => {
+  const message = event.data;
+  switch (message.type) {
+    case "start":
+      await start(message);
+      break;
+    case "force":
+      sim.set_force(message.x, message.y);
+      break;
+    case "add_emitter":
+      emitters.set(
+        message.id,
+        sim.add_emitter(message.x, message.y, message.ballsPerSecond, message.radius)
+      );
+      break;
+    case "remove_emitter":
+      if (emitters.has(message.id)) {
+        sim.remove_emitter(emitters.get(message.id));
+        emitters.delete(message.id);
+      }
+      break;
+    case "add_water_source":
+      sim.add_water_source(message.x, message.y, message.rate);
+      break;
+    case "snapshot": {
+      const snapshot = sim.snapshot();
+      self.postMessage({ type: "snapshot", snapshot }, [snapshot.buffer]);
+      break;
+    }
+    case "restore":
+      // emitters carry on across a restore, so their ids stay as they were
+      sim.restore(message.snapshot);
+      break;
+  }
+};